@@ -0,0 +1,200 @@
+//! Registry aliases.
+//!
+//! A registry may be reachable at several index URLs (a sparse index, a git
+//! mirror, a staging hostname, ...). Alias rules map each of them to a single
+//! canonical URL before the registry config and token paths are resolved, so
+//! they all share the same stored token.
+
+use std::borrow::Cow;
+
+/// Rules mapping index URLs to their canonical URL, configured in the
+/// `[aliases]` table of the config file.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Aliases {
+    /// Exact `alias -> canonical` index URLs.
+    pub(crate) urls: Vec<(String, String)>,
+
+    /// URL prefixes replaced with their canonical prefix.
+    pub(crate) prefixes: Vec<(String, String)>,
+
+    /// Index hosts (including any port) replaced with their canonical host.
+    pub(crate) hosts: Vec<(String, String)>,
+}
+
+impl Aliases {
+    /// Return the canonical URL of the registry at `index_url`.
+    ///
+    ///   1. If `index_url` is an alias in [`Aliases::urls`], its canonical URL
+    ///      is returned.
+    ///   2. Otherwise the host is rewritten by [`Aliases::hosts`], and then the
+    ///      longest matching prefix in [`Aliases::prefixes`] is replaced.
+    ///
+    /// Trailing slashes are ignored when matching exact URLs. If no rule
+    /// matches, `index_url` is returned unchanged.
+    pub(crate) fn resolve<'a>(&self, index_url: &'a str) -> Cow<'a, str> {
+        let trimmed = index_url.trim_end_matches('/');
+        if let Some((_, v)) = self
+            .urls
+            .iter()
+            .find(|(k, _)| k.trim_end_matches('/') == trimmed)
+        {
+            return Cow::Owned(v.clone());
+        }
+
+        let url = self.rewrite_host(index_url);
+
+        let prefix = self
+            .prefixes
+            .iter()
+            .filter(|(k, _)| url.starts_with(k.as_str()))
+            .max_by_key(|(k, _)| k.len());
+
+        match prefix {
+            Some((k, v)) => Cow::Owned(format!("{v}{}", &url[k.len()..])),
+            None => url,
+        }
+    }
+
+    /// Replace the host of `url` if it is listed in [`Aliases::hosts`].
+    fn rewrite_host<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let Some(start) = url.find("://").map(|v| v + 3) else {
+            return Cow::Borrowed(url);
+        };
+
+        let end = url[start..]
+            .find(['/', '?', '#'])
+            .map_or(url.len(), |v| start + v);
+
+        // Skip any user info preceding the host.
+        let start = url[start..end].rfind('@').map_or(start, |v| start + v + 1);
+
+        let host = &url[start..end];
+        match self
+            .hosts
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(host))
+        {
+            Some((_, v)) => Cow::Owned(format!("{}{v}{}", &url[..start], &url[end..])),
+            None => Cow::Borrowed(url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const CANONICAL: &str = "sparse+https://registry.itsallbroken.com/index/";
+
+    fn aliases() -> Aliases {
+        Aliases {
+            urls: vec![(
+                "https://git.itsallbroken.com/index.git".to_string(),
+                CANONICAL.to_string(),
+            )],
+            prefixes: vec![
+                (
+                    "sparse+https://mirror.itsallbroken.com/".to_string(),
+                    "sparse+https://registry.itsallbroken.com/".to_string(),
+                ),
+                (
+                    "sparse+https://mirror.itsallbroken.com/old/".to_string(),
+                    "sparse+https://registry.itsallbroken.com/".to_string(),
+                ),
+            ],
+            hosts: vec![(
+                "staging.itsallbroken.com".to_string(),
+                "registry.itsallbroken.com".to_string(),
+            )],
+        }
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let a = aliases();
+        assert_eq!(
+            a.resolve("https://git.itsallbroken.com/index.git"),
+            CANONICAL
+        );
+        assert_eq!(
+            a.resolve("https://git.itsallbroken.com/index.git/"),
+            CANONICAL
+        );
+    }
+
+    #[test]
+    fn test_resolve_host() {
+        let a = aliases();
+        assert_eq!(
+            a.resolve("sparse+https://staging.itsallbroken.com/index/"),
+            CANONICAL
+        );
+        assert_eq!(
+            a.resolve("sparse+https://user@Staging.ItsAllBroken.com/index/"),
+            "sparse+https://user@registry.itsallbroken.com/index/"
+        );
+
+        // Only the host is matched.
+        assert_eq!(
+            a.resolve("sparse+https://bananas.com/staging.itsallbroken.com/"),
+            "sparse+https://bananas.com/staging.itsallbroken.com/"
+        );
+    }
+
+    #[test]
+    fn test_resolve_prefix() {
+        let a = aliases();
+        assert_eq!(
+            a.resolve("sparse+https://mirror.itsallbroken.com/index/"),
+            CANONICAL
+        );
+
+        // The longest prefix is replaced.
+        assert_eq!(
+            a.resolve("sparse+https://mirror.itsallbroken.com/old/index/"),
+            CANONICAL
+        );
+    }
+
+    #[test]
+    fn test_resolve_no_match() {
+        let a = aliases();
+        assert!(matches!(
+            a.resolve("sparse+https://bananas.com/index/"),
+            Cow::Borrowed("sparse+https://bananas.com/index/")
+        ));
+        assert_eq!(Aliases::default().resolve(CANONICAL), CANONICAL);
+    }
+
+    proptest! {
+        /// A URL on a host no rule matches resolves unchanged.
+        #[test]
+        fn prop_resolve_unaliased_unchanged(
+            host in "[a-z]{1,10}\\.example",
+            path in any::<String>(),
+        ) {
+            let url = format!("sparse+https://{host}/{path}");
+            prop_assert!(matches!(aliases().resolve(&url), Cow::Borrowed(v) if v == url));
+        }
+
+        /// Rewriting an aliased host or prefix keeps the rest of the URL, and
+        /// the canonical URL it resolves to is not itself rewritten.
+        #[test]
+        fn prop_resolve_keeps_suffix(
+            alias in proptest::sample::select(vec![
+                "sparse+https://staging.itsallbroken.com/",
+                "sparse+https://mirror.itsallbroken.com/",
+            ]),
+            suffix in "[a-z0-9._-]{0,10}(/[a-z0-9._-]{1,10}){0,3}",
+        ) {
+            prop_assume!(!suffix.starts_with("old"));
+
+            let a = aliases();
+            let got = a.resolve(&format!("{alias}{suffix}")).into_owned();
+            prop_assert_eq!(&got, &format!("sparse+https://registry.itsallbroken.com/{suffix}"));
+            prop_assert_eq!(a.resolve(&got), got.as_str());
+        }
+    }
+}
@@ -0,0 +1,716 @@
+//! Credential provider argument parsing.
+
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    config::{expand_home, RegistryConfig},
+    pass::{direct_gpg_from_env, BackendKind, PassKeychain, OPERATIONS},
+    token,
+};
+
+/// The env var that enables debug output when set to `1` or `true`.
+pub(crate) const DEBUG_ENV: &str = "CARGO_CREDENTIAL_PASS_DEBUG";
+
+/// The env var used to set the token expiry warning window when not specified
+/// in the provider arguments.
+pub(crate) const EXPIRY_WARNING_ENV: &str = "CARGO_CREDENTIAL_PASS_EXPIRY_WARNING";
+
+/// The default window before a token expires in which a warning is printed.
+const DEFAULT_EXPIRY_WARNING: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The env var used to set the subprocess timeout when not specified in the
+/// provider arguments.
+pub(crate) const TIMEOUT_ENV: &str = "CARGO_CREDENTIAL_PASS_TIMEOUT";
+
+/// The default duration after which a `pass` subprocess is killed.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// How long cargo may cache a token for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum CachePolicy {
+    /// Cache the token until the expiry time in the entry metadata, or for the
+    /// session if there is none.
+    #[default]
+    Expires,
+    /// Cache the token for the session, regardless of the expiry time.
+    Session,
+    /// Never cache the token.
+    Never,
+}
+
+impl FromStr for CachePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expires" => Ok(Self::Expires),
+            "session" => Ok(Self::Session),
+            "never" => Ok(Self::Never),
+            v => Err(format!(
+                "unknown cache policy '{v}' (expected one of: expires, session, never)"
+            )),
+        }
+    }
+}
+
+/// Configuration specified in the cargo credential provider arguments.
+///
+/// Accepts at most one positional argument (the token path), `key=value`
+/// options and `--flag` switches. Settings not specified in the arguments may be
+/// provided by the config file (see [`ProviderArgs::with_config()`]).
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ProviderArgs<'a> {
+    /// The token path or directory, relative to the password store root.
+    pub(crate) path: Option<&'a str>,
+
+    /// The password manager used to store tokens (`backend=<name>`).
+    pub(crate) backend: Option<BackendKind>,
+
+    /// The password store root directory (`store-dir=<dir>`).
+    pub(crate) store_dir: Option<PathBuf>,
+
+    /// The GPG key to encrypt tokens for, overriding the store `.gpg-id`
+    /// (`store-key=<gpg-id>`).
+    pub(crate) store_key: Option<&'a str>,
+
+    /// The GnuPG home directory holding the store keys (`gnupghome=<dir>`).
+    pub(crate) gnupg_home: Option<PathBuf>,
+
+    /// How long cargo may cache the token for (`cache=<policy>`).
+    pub(crate) cache: Option<CachePolicy>,
+
+    /// Exact token paths for operation-specific tokens (config file only).
+    pub(crate) operation_paths: &'a [(&'static str, String)],
+
+    /// Print the token paths tried to stderr (`--debug`).
+    pub(crate) debug: bool,
+
+    /// Decrypt tokens with `gpg` directly, bypassing the backend CLI
+    /// (`--direct-gpg`).
+    pub(crate) direct_gpg: bool,
+
+    /// Warn when a token expires within this duration
+    /// (`expiry-warning=<duration>`).
+    pub(crate) expiry_warning: Option<Duration>,
+
+    /// Open the token entry in `$EDITOR` on login when no token is provided,
+    /// instead of prompting for it (`--editor`).
+    pub(crate) editor: bool,
+
+    /// Kill `pass` subprocesses that do not complete within this duration,
+    /// with `0` disabling the timeout (`timeout=<duration>`).
+    pub(crate) timeout: Option<Duration>,
+
+    /// A regex the whole token must match to be stored on login
+    /// (`token-pattern=<regex>`).
+    pub(crate) token_pattern: Option<&'a str>,
+
+    /// Open the registry login URL in a browser when prompting for a token
+    /// (`--open-url`).
+    pub(crate) open_url: bool,
+
+    /// Verify the token with the registry API before storing it on login
+    /// (`--verify`).
+    pub(crate) verify: bool,
+
+    /// The operation-specific token to store or delete on login / logout
+    /// (`operation=<name>`).
+    pub(crate) operation: Option<&'static str>,
+
+    /// Store an asymmetric (PASETO) secret key on login instead of a token
+    /// (`--paseto`).
+    pub(crate) paseto: bool,
+
+    /// The subject of the asymmetric key, implying `--paseto`
+    /// (`key-subject=<subject>`).
+    pub(crate) key_subject: Option<&'a str>,
+}
+
+impl<'a> ProviderArgs<'a> {
+    /// Parse the provider `args` passed by cargo.
+    ///
+    /// Options are accepted as `key=value` pairs (see [`OPTIONS`]), or in the
+    /// legacy `--key=value` form. A single positional argument is accepted as
+    /// the token path, equivalent to `path=<path>`. Each option may be
+    /// specified at most once.
+    pub(crate) fn parse(args: &[&'a str]) -> Result<Self, cargo_credential::Error> {
+        let mut out = Self::default();
+        let mut seen = Vec::new();
+        let mut positional = false;
+
+        for &arg in args {
+            if FLAGS.contains(&arg) {
+                mark_seen(&mut seen, arg)?;
+                out.set_flag(arg);
+                continue;
+            }
+
+            match split_option(arg)? {
+                Some((key, v)) => {
+                    mark_seen(&mut seen, key)?;
+                    out.set(key, v)?;
+                }
+                // Accept exactly 0 or 1 positional arguments.
+                None if positional => {
+                    return Err(
+                        "too many arguments specified in cargo credential provider config".into(),
+                    )
+                }
+                None => {
+                    mark_seen(&mut seen, "path")?;
+                    positional = true;
+                    out.path = Some(arg);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Enable the `--flag` (one of [`FLAGS`]).
+    fn set_flag(&mut self, flag: &str) {
+        match flag {
+            "--debug" => self.debug = true,
+            "--direct-gpg" => self.direct_gpg = true,
+            "--editor" => self.editor = true,
+            "--open-url" => self.open_url = true,
+            "--verify" => self.verify = true,
+            "--paseto" => self.paseto = true,
+            _ => unreachable!("unknown flag {flag}"),
+        }
+    }
+
+    /// Set the option `key` (one of [`OPTIONS`]) to `v`.
+    fn set(&mut self, key: &str, v: &'a str) -> Result<(), cargo_credential::Error> {
+        match key {
+            "path" => self.path = Some(v),
+            "store-dir" => self.store_dir = Some(absolute_dir(v)?),
+            "store-key" => self.store_key = Some(v),
+            "gnupghome" => self.gnupg_home = Some(absolute_dir(v)?),
+            "backend" => self.backend = Some(v.parse::<BackendKind>()?),
+            "cache" => self.cache = Some(v.parse::<CachePolicy>()?),
+            "timeout" => self.timeout = Some(parse_duration(v)?),
+            "expiry-warning" => self.expiry_warning = Some(parse_duration(v)?),
+            "token-pattern" => self.token_pattern = Some(v),
+            "operation" => {
+                let op = OPERATIONS.iter().find(|&&op| op == v).ok_or_else(|| {
+                    format!(
+                        "unknown operation '{v}' (expected one of: {})",
+                        OPERATIONS.join(", ")
+                    )
+                })?;
+                self.operation = Some(op);
+            }
+            "key-subject" => {
+                self.key_subject = Some(v);
+                self.paseto = true;
+            }
+            _ => unreachable!("unknown option {key}"),
+        }
+
+        Ok(())
+    }
+
+    /// Fill in any settings not specified in the args from the registry
+    /// `config`, if any.
+    ///
+    /// Flags enabled in either the args or the config are enabled.
+    pub(crate) fn with_config(self, config: Option<&'a RegistryConfig>) -> Self {
+        let Some(c) = config else {
+            return self;
+        };
+
+        Self {
+            path: self.path.or(c.path.as_deref()),
+            backend: self.backend.or(c.backend),
+            store_dir: self.store_dir.or_else(|| c.store_dir.clone()),
+            store_key: self.store_key.or(c.store_key.as_deref()),
+            gnupg_home: self.gnupg_home.or_else(|| c.gnupg_home.clone()),
+            cache: self.cache.or(c.cache),
+            operation_paths: &c.operations,
+            token_pattern: self.token_pattern.or(c.token_pattern.as_deref()),
+            verify: self.verify || c.verify,
+            ..self
+        }
+    }
+
+    /// Return the [`BackendKind`] specified in the args, falling back to the
+    /// env and then the default.
+    pub(crate) fn backend(&self) -> Result<BackendKind, cargo_credential::Error> {
+        if let Some(v) = self.backend {
+            return Ok(v);
+        }
+
+        Ok(BackendKind::from_env()?.unwrap_or_default())
+    }
+
+    /// Returns true if debug output is enabled in the args or env.
+    pub(crate) fn debug(&self) -> bool {
+        self.debug || matches!(std::env::var(DEBUG_ENV).as_deref(), Ok("1") | Ok("true"))
+    }
+
+    /// Returns true if direct `gpg` decryption is enabled in the args or env.
+    pub(crate) fn direct_gpg(&self) -> bool {
+        self.direct_gpg || direct_gpg_from_env()
+    }
+
+    /// Return the token expiry warning window specified in the args, falling
+    /// back to the env and then the default of 7 days.
+    pub(crate) fn expiry_warning(&self) -> Result<Duration, cargo_credential::Error> {
+        if let Some(v) = self.expiry_warning {
+            return Ok(v);
+        }
+
+        match std::env::var(EXPIRY_WARNING_ENV) {
+            Ok(v) => Ok(parse_duration(&v)?),
+            Err(_) => Ok(DEFAULT_EXPIRY_WARNING),
+        }
+    }
+
+    /// Return the subprocess timeout specified in the args, falling back to the
+    /// env and then the default of 2 minutes.
+    ///
+    /// Returns [`None`] if the timeout is disabled (set to `0`).
+    pub(crate) fn timeout(&self) -> Result<Option<Duration>, cargo_credential::Error> {
+        let v = match self.timeout {
+            Some(v) => v,
+            None => match std::env::var(TIMEOUT_ENV) {
+                Ok(v) => parse_duration(&v)?,
+                Err(_) => DEFAULT_TIMEOUT,
+            },
+        };
+
+        Ok(Some(v).filter(|v| !v.is_zero()))
+    }
+
+    /// Construct the [`PassKeychain`] configured by the args.
+    pub(crate) fn keychain(&self) -> Result<PassKeychain, cargo_credential::Error> {
+        Ok(PassKeychain::new(self.backend()?.into_backend())
+            .with_store_dir(self.store_dir.clone())
+            .with_store_key(self.store_key.map(ToString::to_string))
+            .with_gnupg_home(self.gnupg_home.clone())
+            .with_direct_gpg(self.direct_gpg())
+            .with_timeout(self.timeout()?))
+    }
+
+    /// Compile the token pattern specified in the args, if any.
+    pub(crate) fn token_pattern(&self) -> Result<Option<token::Pattern>, cargo_credential::Error> {
+        Ok(self.token_pattern.map(token::Pattern::new).transpose()?)
+    }
+}
+
+/// The switches accepted as provider args.
+const FLAGS: [&str; 6] = [
+    "--debug",
+    "--direct-gpg",
+    "--editor",
+    "--open-url",
+    "--verify",
+    "--paseto",
+];
+
+/// The options accepted as `key=value` (or `--key=value`) provider args.
+const OPTIONS: [&str; 11] = [
+    "path",
+    "store-dir",
+    "store-key",
+    "gnupghome",
+    "backend",
+    "cache",
+    "timeout",
+    "expiry-warning",
+    "token-pattern",
+    "operation",
+    "key-subject",
+];
+
+/// Deprecated spellings of [`OPTIONS`], accepted as an alias of the option.
+const DEPRECATED_OPTIONS: [(&str, &str); 1] = [("store", "store-dir")];
+
+/// Expand a leading `~/` in the directory `v`, which must then be absolute.
+fn absolute_dir(v: &str) -> Result<PathBuf, String> {
+    let dir = expand_home(v);
+    if !dir.is_absolute() {
+        return Err(format!("directory '{v}' must be absolute"));
+    }
+    Ok(dir)
+}
+
+/// Record the option `key` as specified, returning an error if it has already
+/// been specified.
+fn mark_seen<'a>(seen: &mut Vec<&'a str>, key: &'a str) -> Result<(), String> {
+    if seen.contains(&key) {
+        return Err(format!("duplicate credential provider option '{key}'"));
+    }
+    seen.push(key);
+    Ok(())
+}
+
+/// Split `arg` into an option key (one of [`OPTIONS`]) and value, returning
+/// [`None`] for a positional argument.
+///
+/// An argument is an option if it starts with `--`, or if the text before the
+/// first `=` is a bare `key` (lowercase letters and `-` only) - a token path
+/// with such a prefix can be specified as `path=<path>`.
+fn split_option(arg: &str) -> Result<Option<(&'static str, &str)>, String> {
+    let (key, v) = match arg.strip_prefix("--") {
+        Some(rest) => rest.split_once('=').unwrap_or((rest, "")),
+        None => match arg.split_once('=') {
+            Some((k, v))
+                if !k.is_empty() && k.chars().all(|c| c.is_ascii_lowercase() || c == '-') =>
+            {
+                (k, v)
+            }
+            _ => return Ok(None),
+        },
+    };
+
+    let key = DEPRECATED_OPTIONS
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, v)| v);
+
+    match OPTIONS.iter().find(|&&v| v == key) {
+        Some(key) if arg.contains('=') => Ok(Some((key, v))),
+        Some(key) => Err(format!(
+            "missing value for credential provider option '{arg}' (expected {key}=<value>)"
+        )),
+        None if arg.starts_with("--") => Err(format!("unknown credential provider option '{arg}'")),
+        None => Err(format!(
+            "unknown credential provider option '{key}' in '{arg}' (expected one of: {})",
+            OPTIONS.join(", ")
+        )),
+    }
+}
+
+/// Parse a human-readable duration such as `30s`, `5m`, `12h` or `7d`.
+///
+/// A value without a unit suffix is interpreted as seconds.
+pub(crate) fn parse_duration(v: &str) -> Result<Duration, String> {
+    let (n, unit) = match v.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => v.split_at(idx),
+        None => (v, "s"),
+    };
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration '{v}' (expected e.g. 30s, 5m, 12h, 7d)"
+            ))
+        }
+    };
+
+    n.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration '{v}' (expected e.g. 30s, 5m, 12h, 7d)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty() {
+        let got = ProviderArgs::parse(&[]).expect("valid args");
+        assert_eq!(got, ProviderArgs::default());
+    }
+
+    #[test]
+    fn test_parse_path_and_backend() {
+        let got = ProviderArgs::parse(&["--backend=gopass", "tokens/"]).expect("valid args");
+        assert_eq!(
+            got,
+            ProviderArgs {
+                path: Some("tokens/"),
+                backend: Some(BackendKind::Gopass),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        let got = ProviderArgs::parse(&[
+            "path=work/",
+            "store-dir=/srv/store",
+            "backend=gopass",
+            "cache=expires",
+            "timeout=30s",
+        ])
+        .expect("valid args");
+
+        assert_eq!(
+            got,
+            ProviderArgs {
+                path: Some("work/"),
+                store_dir: Some(PathBuf::from("/srv/store")),
+                backend: Some(BackendKind::Gopass),
+                cache: Some(CachePolicy::Expires),
+                timeout: Some(Duration::from_secs(30)),
+                ..Default::default()
+            }
+        );
+
+        // The legacy --key=value form is equivalent.
+        let legacy = ProviderArgs::parse(&["--backend=gopass", "--timeout=30s"]).unwrap();
+        let got = ProviderArgs::parse(&["backend=gopass", "timeout=30s"]).unwrap();
+        assert_eq!(got, legacy);
+
+        // A path containing a '=' can be specified with path=.
+        let got = ProviderArgs::parse(&["path=tokens/a=b"]).expect("valid args");
+        assert_eq!(got.path, Some("tokens/a=b"));
+    }
+
+    #[test]
+    fn test_parse_store() {
+        let got = ProviderArgs::parse(&[
+            "store-dir=/srv/store",
+            "store-key=bananas@itsallbroken.com",
+            "gnupghome=/srv/gnupg",
+        ])
+        .expect("valid args");
+
+        assert_eq!(got.store_dir, Some(PathBuf::from("/srv/store")));
+        assert_eq!(got.store_key, Some("bananas@itsallbroken.com"));
+        assert_eq!(got.gnupg_home, Some(PathBuf::from("/srv/gnupg")));
+
+        // The deprecated store= spelling is an alias of store-dir=.
+        let got = ProviderArgs::parse(&["store=/srv/store"]).expect("valid args");
+        assert_eq!(got.store_dir, Some(PathBuf::from("/srv/store")));
+
+        let got = ProviderArgs::parse(&["store=/srv/store", "store-dir=/srv/store"])
+            .expect_err("duplicate store");
+        assert_eq!(
+            got.to_string(),
+            "duplicate credential provider option 'store-dir'"
+        );
+
+        let got = ProviderArgs::parse(&["store-dir=relative/store"]).expect_err("relative store");
+        assert_eq!(
+            got.to_string(),
+            "directory 'relative/store' must be absolute"
+        );
+
+        let got = ProviderArgs::parse(&["gnupghome=gnupg"]).expect_err("relative gnupghome");
+        assert_eq!(got.to_string(), "directory 'gnupg' must be absolute");
+    }
+
+    #[test]
+    fn test_parse_duplicate() {
+        for (args, want) in [
+            (
+                &["backend=pass", "--backend=gopass"][..],
+                "duplicate credential provider option 'backend'",
+            ),
+            (
+                &["tokens/", "path=work/"],
+                "duplicate credential provider option 'path'",
+            ),
+            (
+                &["path=work/", "tokens/"],
+                "duplicate credential provider option 'path'",
+            ),
+            (
+                &["--verify", "--verify"],
+                "duplicate credential provider option '--verify'",
+            ),
+        ] {
+            let got = ProviderArgs::parse(args).expect_err("duplicate");
+            assert_eq!(got.to_string(), want, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        let got = ProviderArgs::parse(&["bananas=42"]).expect_err("unknown key");
+        assert_eq!(
+            got.to_string(),
+            "unknown credential provider option 'bananas' in 'bananas=42' (expected one of: \
+            path, store-dir, store-key, gnupghome, backend, cache, timeout, expiry-warning, token-pattern, operation, \
+            key-subject)"
+        );
+
+        let got = ProviderArgs::parse(&["--backend"]).expect_err("missing value");
+        assert_eq!(
+            got.to_string(),
+            "missing value for credential provider option '--backend' (expected backend=<value>)"
+        );
+    }
+
+    #[test]
+    fn test_parse_direct_gpg() {
+        let got = ProviderArgs::parse(&["--direct-gpg"]).expect("valid args");
+        assert!(got.direct_gpg);
+        assert_eq!(got.path, None);
+    }
+
+    #[test]
+    fn test_parse_editor() {
+        let got = ProviderArgs::parse(&["--editor"]).expect("valid args");
+        assert!(got.editor);
+    }
+
+    #[test]
+    fn test_parse_open_url() {
+        let got = ProviderArgs::parse(&["--open-url"]).expect("valid args");
+        assert!(got.open_url);
+    }
+
+    #[test]
+    fn test_parse_verify() {
+        let got = ProviderArgs::parse(&["--verify"]).expect("valid args");
+        assert!(got.verify);
+    }
+
+    #[test]
+    fn test_parse_debug() {
+        let got = ProviderArgs::parse(&["--debug"]).expect("valid args");
+        assert!(got.debug);
+    }
+
+    #[test]
+    fn test_parse_expiry_warning() {
+        let got = ProviderArgs::parse(&["--expiry-warning=2d"]).expect("valid args");
+        assert_eq!(
+            got.expiry_warning,
+            Some(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let got = ProviderArgs::parse(&["--timeout=30s"]).expect("valid args");
+        assert_eq!(got.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(got.timeout().unwrap(), Some(Duration::from_secs(30)));
+
+        let got = ProviderArgs::parse(&["--timeout=0"]).expect("valid args");
+        assert_eq!(got.timeout().unwrap(), None);
+
+        let got = ProviderArgs::parse(&["--timeout=soon"]).expect_err("invalid timeout");
+        assert_eq!(
+            got.to_string(),
+            "invalid duration 'soon' (expected e.g. 30s, 5m, 12h, 7d)"
+        );
+    }
+
+    #[test]
+    fn test_parse_token_pattern() {
+        let got = ProviderArgs::parse(&["--token-pattern=tok_.+"]).expect("valid args");
+        assert_eq!(got.token_pattern, Some("tok_.+"));
+        assert!(got.token_pattern().unwrap().is_some());
+
+        let got = ProviderArgs::parse(&["--token-pattern=tok_["]).expect("valid args");
+        assert!(got.token_pattern().is_err());
+    }
+
+    #[test]
+    fn test_parse_operation() {
+        let got = ProviderArgs::parse(&["--operation=publish"]).expect("valid args");
+        assert_eq!(got.operation, Some("publish"));
+
+        let got = ProviderArgs::parse(&["--operation=read"]).expect_err("invalid operation");
+        assert_eq!(
+            got.to_string(),
+            "unknown operation 'read' (expected one of: publish, yank, owners)"
+        );
+    }
+
+    #[test]
+    fn test_parse_paseto() {
+        let got = ProviderArgs::parse(&["--paseto"]).expect("valid args");
+        assert!(got.paseto);
+        assert_eq!(got.key_subject, None);
+
+        let got = ProviderArgs::parse(&["--key-subject=bananas"]).expect("valid args");
+        assert!(got.paseto);
+        assert_eq!(got.key_subject, Some("bananas"));
+    }
+
+    #[test]
+    fn test_with_config() {
+        let config = RegistryConfig {
+            path: Some("config/".to_string()),
+            backend: Some(BackendKind::Gopass),
+            cache: Some(CachePolicy::Never),
+            operations: vec![("publish", "config/publish".to_string())],
+            verify: true,
+            ..Default::default()
+        };
+
+        // Settings in the args take precedence over the config.
+        let got = ProviderArgs::parse(&["args/", "--backend=passage"])
+            .expect("valid args")
+            .with_config(Some(&config));
+
+        assert_eq!(
+            got,
+            ProviderArgs {
+                path: Some("args/"),
+                backend: Some(BackendKind::Passage),
+                cache: Some(CachePolicy::Never),
+                operation_paths: &config.operations,
+                verify: true,
+                ..Default::default()
+            }
+        );
+
+        let got = ProviderArgs::parse(&[])
+            .expect("valid args")
+            .with_config(None);
+        assert_eq!(got, ProviderArgs::default());
+    }
+
+    #[test]
+    fn test_parse_cache_policy() {
+        assert_eq!("expires".parse(), Ok(CachePolicy::Expires));
+        assert_eq!("session".parse(), Ok(CachePolicy::Session));
+        assert_eq!("never".parse(), Ok(CachePolicy::Never));
+        assert!("forever".parse::<CachePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("42"), Ok(Duration::from_secs(42)));
+        assert_eq!(parse_duration("42s"), Ok(Duration::from_secs(42)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+
+        for v in ["", "d", "5w", "-5s", "1.5h", "99999999999999999999d"] {
+            assert!(parse_duration(v).is_err(), "{v} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_parse_unknown_backend() {
+        let got = ProviderArgs::parse(&["--backend=bananas"]).expect_err("invalid backend");
+        assert_eq!(
+            got.to_string(),
+            "unknown backend 'bananas' (expected one of: pass, gopass, passage)"
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_option() {
+        let got = ProviderArgs::parse(&["--bananas"]).expect_err("invalid option");
+        assert_eq!(
+            got.to_string(),
+            "unknown credential provider option '--bananas'"
+        );
+    }
+
+    #[test]
+    fn test_parse_too_many_paths() {
+        let got = ProviderArgs::parse(&["1", "2"]).expect_err("too many args");
+        assert_eq!(
+            got.to_string(),
+            "too many arguments specified in cargo credential provider config"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! A standalone command line interface for managing tokens outside of cargo.
+//!
+//! Commands resolve token paths using the same provider arguments and
+//! [`PassPathBuilder`] logic as the cargo credential provider, so tokens can be
+//! managed (and path configuration debugged) without crafting credential
+//! protocol requests by hand.
+//!
+//! [`PassPathBuilder`]: crate::pass::PassPathBuilder
+
+use std::{
+    io::{BufRead, IsTerminal},
+    path::Path,
+};
+
+use cargo_credential::{Action, Credential, LoginOptions, RegistryInfo, Secret};
+
+use crate::{
+    args::ProviderArgs,
+    candidate_paths,
+    config::Config,
+    delete_candidates, doctor,
+    import::Credentials,
+    pass::OPERATIONS,
+    path_from_args,
+    registry::{CRATES_IO, CRATES_IO_INDEX},
+    Dispatch,
+};
+
+/// A CLI subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    /// List the tokens stored under the token directory.
+    List,
+    /// Print the token path used for a registry.
+    ShowPath,
+    /// Store a token for a registry.
+    Login,
+    /// Delete the token for a registry.
+    Logout,
+    /// Show which tokens are stored for a registry.
+    Status,
+    /// Import plaintext tokens from cargo's credentials file.
+    Import,
+    /// Diagnose problems with the password store environment.
+    Doctor,
+}
+
+impl Command {
+    /// Parse the subcommand `name`, returning [`None`] if it is not a known
+    /// command.
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "list" => Some(Self::List),
+            "show-path" => Some(Self::ShowPath),
+            "login" => Some(Self::Login),
+            "logout" => Some(Self::Logout),
+            "status" => Some(Self::Status),
+            "import" => Some(Self::Import),
+            "doctor" => Some(Self::Doctor),
+            _ => None,
+        }
+    }
+
+    /// Execute this command with the command line `args` following the
+    /// subcommand name.
+    pub(crate) fn run(self, args: &[&str]) -> Result<(), cargo_credential::Error> {
+        match self {
+            Self::List => return list(&ProviderArgs::parse(args)?),
+            Self::Import => return import(args),
+            Self::Doctor => return doctor(args),
+            _ => {}
+        }
+
+        let [registry, args @ ..] = args else {
+            return Err("missing registry name or index URL argument".into());
+        };
+        let registry = registry_info(registry);
+
+        // Token paths are derived from the canonical URL of an aliased index.
+        let config = Config::load()?;
+        let index_url = config.aliases().resolve(registry.index_url);
+        let canonical = RegistryInfo {
+            index_url: &index_url,
+            name: registry.name,
+            headers: vec![],
+        };
+
+        // Login applies the config file settings in the credential provider.
+        if self == Self::Login {
+            return login(&registry, &canonical, &config, args);
+        }
+
+        let args = ProviderArgs::parse(args)?.with_config(config.registry(&canonical));
+        let registry = canonical;
+
+        match self {
+            Self::List | Self::Import | Self::Doctor | Self::Login => unreachable!(),
+            Self::ShowPath => {
+                println!(
+                    "{}",
+                    path_from_args(&args, &registry, args.operation, None)?
+                );
+                Ok(())
+            }
+            Self::Logout => logout(&registry, &args),
+            Self::Status => status(&registry, &args),
+        }
+    }
+}
+
+/// Construct a [`RegistryInfo`] from a registry name, or index URL.
+fn registry_info(v: &str) -> RegistryInfo<'_> {
+    if v.contains("://") {
+        return RegistryInfo {
+            index_url: v,
+            name: None,
+            headers: vec![],
+        };
+    }
+
+    RegistryInfo {
+        index_url: "",
+        name: Some(v),
+        headers: vec![],
+    }
+}
+
+/// Print the tokens stored under the configured token directory.
+fn list(args: &ProviderArgs<'_>) -> Result<(), cargo_credential::Error> {
+    let dir = match args.path {
+        Some(v) if v.ends_with('/') => v,
+        Some(_) => return Err("list requires a token directory path (ending in '/')".into()),
+        None => "cargo-registry",
+    };
+
+    for path in args.keychain()?.list_tokens(Path::new(dir))? {
+        println!("{path}");
+    }
+
+    Ok(())
+}
+
+/// Check the environment the provider configured by `args` runs in.
+///
+/// If `--registry=<registry-or-url>` is specified, the config file settings
+/// for that registry are applied.
+fn doctor(args: &[&str]) -> Result<(), cargo_credential::Error> {
+    let registry = args.iter().find_map(|v| v.strip_prefix("--registry="));
+    let args = args
+        .iter()
+        .copied()
+        .filter(|v| !v.starts_with("--registry="))
+        .collect::<Vec<_>>();
+
+    let config = Config::load()?;
+    let mut args = ProviderArgs::parse(&args)?;
+    if let Some(registry) = registry {
+        let registry = registry_info(registry);
+        let index_url = config.aliases().resolve(registry.index_url);
+        let canonical = RegistryInfo {
+            index_url: &index_url,
+            name: registry.name,
+            headers: vec![],
+        };
+        args = args.with_config(config.registry(&canonical));
+    }
+
+    if !doctor::run(&args)? {
+        return Err("problems found, see above for fixes".into());
+    }
+    Ok(())
+}
+
+/// Import the plaintext tokens in cargo's credentials file into the password
+/// store, verifying each can be read back.
+///
+/// If `--rewrite` is specified, the imported tokens are then removed from the
+/// credentials file, keeping a backup of the original.
+fn import(args: &[&str]) -> Result<(), cargo_credential::Error> {
+    let rewrite = args.contains(&"--rewrite");
+    let args = args
+        .iter()
+        .copied()
+        .filter(|v| *v != "--rewrite")
+        .collect::<Vec<_>>();
+    let config = Config::load()?;
+
+    let path = Credentials::default_path().ok_or("cannot determine the cargo home directory")?;
+    let mut credentials = Credentials::load(&path)?;
+    let tokens = credentials.tokens()?;
+
+    if tokens.is_empty() {
+        eprintln!("no tokens found in {}", path.display());
+        return Ok(());
+    }
+
+    // An exact token path can only hold a single token.
+    let path_arg = ProviderArgs::parse(&args)?.path;
+    if tokens.len() > 1 && path_arg.is_some_and(|v| !v.ends_with('/')) {
+        return Err(
+            "importing multiple tokens requires a token directory path (ending in '/')".into(),
+        );
+    }
+
+    for t in &tokens {
+        let index_url = match t.registry.as_str() {
+            CRATES_IO => CRATES_IO_INDEX,
+            _ => "",
+        };
+
+        // As for login, the config file settings of the (canonical) registry
+        // apply to each token.
+        let index_url = config.aliases().resolve(index_url);
+        let registry = RegistryInfo {
+            index_url: &index_url,
+            name: Some(&t.registry),
+            headers: vec![],
+        };
+        let args = ProviderArgs::parse(&args)?.with_config(config.registry(&registry));
+        let keychain = args.keychain()?;
+
+        let path = path_from_args(&args, &registry, args.operation, None)?;
+        keychain.upsert_token(&path, &t.token.as_deref())?;
+
+        let stored = keychain.read_entry(&path)?;
+        if stored.token().expose() != t.token.as_deref().expose() {
+            return Err(
+                format!("token read back from {path} does not match the imported token").into(),
+            );
+        }
+
+        eprintln!("imported {} token into {path}", t.registry);
+    }
+
+    if !rewrite {
+        eprintln!(
+            "plaintext tokens remain in {}, re-run with --rewrite to remove them",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    credentials.remove_tokens(tokens.iter().map(|v| v.registry.as_str()));
+    let backup = credentials.save_with_backup()?;
+
+    eprintln!(
+        "removed tokens from {} (original saved to {})",
+        credentials.path().display(),
+        backup.display()
+    );
+
+    Ok(())
+}
+
+/// Store a token for `registry`, reporting the path it was stored at for the
+/// `canonical` registry.
+///
+/// The token is read from stdin when it is not a terminal, otherwise the
+/// interactive login flow is used.
+fn login(
+    registry: &RegistryInfo<'_>,
+    canonical: &RegistryInfo<'_>,
+    config: &Config,
+    args: &[&str],
+) -> Result<(), cargo_credential::Error> {
+    let token = if std::io::stdin().is_terminal() {
+        None
+    } else {
+        let mut line = String::new();
+        std::io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(Box::new)?;
+        Some(Secret::from(line.trim().to_string()))
+    };
+
+    let opts = LoginOptions {
+        token: token.as_ref().map(|v| v.as_deref()),
+        login_url: None,
+    };
+
+    Dispatch.perform(registry, &Action::Login(opts), args)?;
+
+    let args = ProviderArgs::parse(args)?.with_config(config.registry(canonical));
+    eprintln!(
+        "token stored in {}",
+        path_from_args(&args, canonical, args.operation, None)?
+    );
+
+    Ok(())
+}
+
+/// Delete the token for `registry`.
+fn logout(
+    registry: &RegistryInfo<'_>,
+    args: &ProviderArgs<'_>,
+) -> Result<(), cargo_credential::Error> {
+    let candidates = candidate_paths(args, registry, args.operation, None)?;
+
+    // Report the missing path, rather than the terse "not found" RPC error.
+    let deleted = delete_candidates(&args.keychain()?, &candidates)
+        .map_err(|e| cargo_credential::Error::Other(e.into()))?;
+
+    for path in deleted {
+        eprintln!("token deleted from {path}");
+    }
+    Ok(())
+}
+
+/// Print the token paths for `registry`, and whether a token is stored in
+/// each.
+fn status(
+    registry: &RegistryInfo<'_>,
+    args: &ProviderArgs<'_>,
+) -> Result<(), cargo_credential::Error> {
+    let keychain = args.keychain()?;
+
+    let ops = std::iter::once(None).chain(OPERATIONS.into_iter().map(Some));
+    for op in ops {
+        let path = path_from_args(args, registry, op, None)?;
+        let state = if keychain.contains(&path) {
+            "stored"
+        } else {
+            "not stored"
+        };
+
+        println!("{:<8} {path} ({state})", op.unwrap_or("default"));
+
+        // Tokens at other candidate paths are used if the canonical path
+        // has none.
+        for path in candidate_paths(args, registry, op, None)?.iter().skip(1) {
+            if keychain.contains(path) {
+                println!("{:<8} {path} (stored, fallback)", "");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("list"), Some(Command::List));
+        assert_eq!(Command::parse("show-path"), Some(Command::ShowPath));
+        assert_eq!(Command::parse("login"), Some(Command::Login));
+        assert_eq!(Command::parse("logout"), Some(Command::Logout));
+        assert_eq!(Command::parse("status"), Some(Command::Status));
+        assert_eq!(Command::parse("import"), Some(Command::Import));
+        assert_eq!(Command::parse("doctor"), Some(Command::Doctor));
+        assert_eq!(Command::parse("bananas"), None);
+    }
+
+    #[test]
+    fn test_registry_info_name() {
+        let got = registry_info("bananas");
+        assert_eq!(got.name, Some("bananas"));
+    }
+
+    #[test]
+    fn test_registry_info_url() {
+        let got = registry_info("sparse+https://itsallbroken.com/index/");
+        assert_eq!(got.name, None);
+        assert_eq!(got.index_url, "sparse+https://itsallbroken.com/index/");
+    }
+
+    #[test]
+    fn test_missing_registry() {
+        let got = Command::ShowPath.run(&[]).expect_err("missing registry");
+        assert_eq!(
+            got.to_string(),
+            "missing registry name or index URL argument"
+        );
+    }
+}
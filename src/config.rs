@@ -0,0 +1,626 @@
+//! The (optional) per-registry configuration file.
+//!
+//! Settings are keyed by registry name or index URL:
+//!
+//! ```toml
+//! [registries.crates-io]
+//! path = "personal/crates-io.token"
+//!
+//! [registries."sparse+https://registry.example.com/index/"]
+//! backend = "gopass"
+//! store-dir = "~/.password-store-work"
+//! gnupghome = "~/.gnupg-work"
+//! cache = "never"
+//! token-pattern = "ex_[a-z0-9]{32}"
+//! verify = true
+//!
+//! [registries."sparse+https://registry.example.com/index/".operations]
+//! publish = "work/publish.token"
+//! ```
+//!
+//! Other index URLs of a registry can be mapped to its canonical URL (see
+//! [`Aliases`]):
+//!
+//! ```toml
+//! [aliases.urls]
+//! "https://git.example.com/index.git" = "sparse+https://registry.example.com/index/"
+//!
+//! [aliases.prefixes]
+//! "sparse+https://mirror.example.com/" = "sparse+https://registry.example.com/"
+//!
+//! [aliases.hosts]
+//! "staging.example.com" = "registry.example.com"
+//! ```
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use cargo_credential::RegistryInfo;
+use thiserror::Error;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+use crate::{
+    alias::Aliases,
+    args::CachePolicy,
+    pass::{BackendKind, OPERATIONS},
+    token,
+};
+
+/// The env var used to override the path of the config file.
+pub(crate) const CONFIG_ENV: &str = "CARGO_CREDENTIAL_PASS_CONFIG";
+
+/// The keys accepted in a registry table.
+const REGISTRY_KEYS: [&str; 9] = [
+    "path",
+    "backend",
+    "store-dir",
+    "store-key",
+    "gnupghome",
+    "cache",
+    "operations",
+    "token-pattern",
+    "verify",
+];
+
+/// The keys accepted at the top level.
+const TOP_LEVEL_KEYS: [&str; 2] = ["registries", "aliases"];
+
+/// The kinds of alias rule accepted in the `[aliases]` table.
+const ALIAS_KEYS: [&str; 3] = ["urls", "prefixes", "hosts"];
+
+/// Failures loading the config file.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    /// The config file could not be read.
+    #[error("error reading {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    /// The config file is not valid TOML.
+    #[error("error parsing {0}: {1}")]
+    Parse(PathBuf, toml_edit::TomlError),
+
+    /// A table contains a key that is not recognised.
+    #[error("unknown key '{key}' in {table} of {file} (expected one of: {expected})")]
+    UnknownKey {
+        file: PathBuf,
+        table: String,
+        key: String,
+        expected: String,
+    },
+
+    /// A key has a value of the wrong type, or an invalid value.
+    #[error("invalid value for '{key}' in {table} of {file}: {reason}")]
+    InvalidValue {
+        file: PathBuf,
+        table: String,
+        key: String,
+        reason: String,
+    },
+}
+
+/// The settings for a single registry.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RegistryConfig {
+    /// The token path or directory, relative to the password store root.
+    pub(crate) path: Option<String>,
+
+    /// The password manager used to store tokens.
+    pub(crate) backend: Option<BackendKind>,
+
+    /// The password store root directory.
+    pub(crate) store_dir: Option<PathBuf>,
+
+    /// The GPG key to encrypt tokens for, overriding the store `.gpg-id`.
+    pub(crate) store_key: Option<String>,
+
+    /// The GnuPG home directory holding the store keys.
+    pub(crate) gnupg_home: Option<PathBuf>,
+
+    /// How long cargo may cache the token for.
+    pub(crate) cache: Option<CachePolicy>,
+
+    /// Exact token paths for operation-specific tokens.
+    pub(crate) operations: Vec<(&'static str, String)>,
+
+    /// A regex the whole token must match to be stored on login.
+    pub(crate) token_pattern: Option<String>,
+
+    /// Verify the token with the registry API before storing it on login.
+    pub(crate) verify: bool,
+}
+
+/// The parsed config file.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Config {
+    /// Registry settings, keyed by registry name or index URL.
+    registries: Vec<(String, RegistryConfig)>,
+
+    /// Rules mapping index URLs to the canonical URL of a registry.
+    aliases: Aliases,
+}
+
+impl Config {
+    /// Return the path of the config file - the value of [`CONFIG_ENV`] if
+    /// set, otherwise `cargo-credential-pass/config.toml` in
+    /// `$XDG_CONFIG_HOME` (or `~/.config`).
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        if let Some(v) = std::env::var_os(CONFIG_ENV) {
+            return Some(PathBuf::from(v));
+        }
+
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|v| v.is_absolute())
+            .or_else(|| std::env::home_dir().map(|v| v.join(".config")))
+            .map(|v| v.join("cargo-credential-pass/config.toml"))
+    }
+
+    /// Load the config file at [`Config::default_path()`], returning an empty
+    /// config if it does not exist.
+    pub(crate) fn load() -> Result<Self, Error> {
+        match Self::default_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config file at `path`, returning an empty config if it does
+    /// not exist.
+    pub(crate) fn load_from(path: &Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(v) => Self::parse(path, &v),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Error::Read(path.to_path_buf(), e)),
+        }
+    }
+
+    /// Parse the config `contents` read from `file`.
+    fn parse(file: &Path, contents: &str) -> Result<Self, Error> {
+        let doc = contents
+            .parse::<DocumentMut>()
+            .map_err(|e| Error::Parse(file.to_path_buf(), e))?;
+
+        let p = Parser { file };
+        let mut out = Self::default();
+
+        for (key, item) in doc.iter() {
+            match key {
+                "registries" => {
+                    for (name, item) in p.table("", key, item)?.iter() {
+                        let table = format!("registries.{name}");
+                        let registry = p.table("registries", name, item)?;
+                        out.registries
+                            .push((name.to_string(), p.registry(&table, registry)?));
+                    }
+                }
+                "aliases" => out.aliases = p.aliases(p.table("", key, item)?)?,
+                _ => return Err(p.unknown_key("", key, &TOP_LEVEL_KEYS)),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Return the alias rules mapping index URLs to their canonical URL.
+    pub(crate) fn aliases(&self) -> &Aliases {
+        &self.aliases
+    }
+
+    /// Return the settings for `registry`, matching the registry name in
+    /// preference to the index URL.
+    pub(crate) fn registry(&self, registry: &RegistryInfo<'_>) -> Option<&RegistryConfig> {
+        let by_name = registry.name.and_then(|name| {
+            self.registries
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v)
+        });
+
+        by_name.or_else(|| {
+            let url = registry.index_url.trim_end_matches('/');
+            self.registries
+                .iter()
+                .find(|(k, _)| k.trim_end_matches('/') == url)
+                .map(|(_, v)| v)
+        })
+    }
+}
+
+/// Helpers to extract typed values from the config file, reporting errors with
+/// the location of the invalid key.
+struct Parser<'a> {
+    file: &'a Path,
+}
+
+impl Parser<'_> {
+    /// Parse the settings of the registry `table`.
+    fn registry(&self, table: &str, v: &dyn TableLike) -> Result<RegistryConfig, Error> {
+        let mut out = RegistryConfig::default();
+
+        for (key, item) in v.iter() {
+            match key {
+                "path" => {
+                    let path = self.str(table, key, item)?;
+                    if path.starts_with('/') {
+                        return Err(self.invalid(
+                            table,
+                            key,
+                            "absolute paths are not supported, specify a path relative \
+                            to the password store root",
+                        ));
+                    }
+                    out.path = Some(path.to_string());
+                }
+                "backend" => {
+                    let v = self.str(table, key, item)?;
+                    out.backend = Some(v.parse().map_err(|e| self.invalid(table, key, e))?);
+                }
+                "store-dir" => out.store_dir = Some(self.dir(table, key, item)?),
+                "store-key" => out.store_key = Some(self.str(table, key, item)?.to_string()),
+                "gnupghome" => out.gnupg_home = Some(self.dir(table, key, item)?),
+                "cache" => {
+                    let v = self.str(table, key, item)?;
+                    out.cache = Some(v.parse().map_err(|e| self.invalid(table, key, e))?);
+                }
+                "operations" => {
+                    let ops_table = format!("{table}.operations");
+                    for (op, item) in self.table(table, key, item)?.iter() {
+                        let Some(op) = OPERATIONS.iter().find(|&&v| v == op) else {
+                            return Err(self.unknown_key(&ops_table, op, &OPERATIONS));
+                        };
+                        let path = self.str(&ops_table, op, item)?;
+                        if path.starts_with('/') {
+                            return Err(self.invalid(
+                                &ops_table,
+                                op,
+                                "absolute paths are not supported, specify a path relative \
+                                to the password store root",
+                            ));
+                        }
+                        out.operations.push((op, path.to_string()));
+                    }
+                }
+                "token-pattern" => {
+                    let v = self.str(table, key, item)?;
+                    token::Pattern::new(v).map_err(|e| self.invalid(table, key, e))?;
+                    out.token_pattern = Some(v.to_string());
+                }
+                "verify" => {
+                    out.verify = item
+                        .as_bool()
+                        .ok_or_else(|| self.invalid(table, key, "expected a boolean"))?;
+                }
+                _ => return Err(self.unknown_key(table, key, &REGISTRY_KEYS)),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Parse the `[aliases]` table.
+    fn aliases(&self, v: &dyn TableLike) -> Result<Aliases, Error> {
+        let mut out = Aliases::default();
+
+        for (key, item) in v.iter() {
+            let rules = match key {
+                "urls" => &mut out.urls,
+                "prefixes" => &mut out.prefixes,
+                "hosts" => &mut out.hosts,
+                _ => return Err(self.unknown_key("aliases", key, &ALIAS_KEYS)),
+            };
+
+            let table = format!("aliases.{key}");
+            for (alias, item) in self.table("aliases", key, item)?.iter() {
+                let canonical = self.str(&table, alias, item)?;
+                if canonical.is_empty() {
+                    return Err(self.invalid(&table, alias, "expected a non-empty string"));
+                }
+                if key == "hosts" && canonical.contains(['/', '@']) {
+                    return Err(self.invalid(&table, alias, "expected a host name"));
+                }
+                rules.push((alias.to_string(), canonical.to_string()));
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Return the table value of `key`.
+    fn table<'b>(
+        &self,
+        table: &str,
+        key: &str,
+        item: &'b Item,
+    ) -> Result<&'b dyn TableLike, Error> {
+        item.as_table_like()
+            .ok_or_else(|| self.invalid(table, key, "expected a table"))
+    }
+
+    /// Return the absolute directory value of `key`, expanding a leading `~/`.
+    fn dir(&self, table: &str, key: &str, item: &Item) -> Result<PathBuf, Error> {
+        let dir = expand_home(self.str(table, key, item)?);
+        if !dir.is_absolute() {
+            return Err(self.invalid(table, key, "directory must be absolute"));
+        }
+        Ok(dir)
+    }
+
+    /// Return the string value of `key`.
+    fn str<'b>(&self, table: &str, key: &str, item: &'b Item) -> Result<&'b str, Error> {
+        item.as_str()
+            .ok_or_else(|| self.invalid(table, key, "expected a string"))
+    }
+
+    fn invalid(&self, table: &str, key: &str, reason: impl ToString) -> Error {
+        Error::InvalidValue {
+            file: self.file.to_path_buf(),
+            table: location(table),
+            key: key.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn unknown_key(&self, table: &str, key: &str, expected: &[&str]) -> Error {
+        Error::UnknownKey {
+            file: self.file.to_path_buf(),
+            table: location(table),
+            key: key.to_string(),
+            expected: expected.join(", "),
+        }
+    }
+}
+
+/// Describe the location of `table` in the config file for error messages.
+fn location(table: &str) -> String {
+    match table {
+        "" => "the top level".to_string(),
+        v => format!("[{v}]"),
+    }
+}
+
+/// Expand a leading `~/` in `path` to the user's home directory.
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Config, Error> {
+        Config::parse(Path::new("config.toml"), contents)
+    }
+
+    fn registry<'a>(name: Option<&'a str>, index_url: &'a str) -> RegistryInfo<'a> {
+        RegistryInfo {
+            index_url,
+            name,
+            headers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let got = parse(
+            r#"
+            [registries.crates-io]
+            path = "personal/"
+
+            [registries."sparse+https://itsallbroken.com/index/"]
+            path = "work/token"
+            backend = "gopass"
+            store-dir = "/srv/store"
+            store-key = "bananas@itsallbroken.com"
+            gnupghome = "/srv/gnupg"
+            cache = "never"
+            token-pattern = "tok_.+"
+            verify = true
+            operations = { publish = "work/publish-token" }
+            "#,
+        )
+        .expect("valid config");
+
+        assert_eq!(
+            got.registries,
+            [
+                (
+                    "crates-io".to_string(),
+                    RegistryConfig {
+                        path: Some("personal/".to_string()),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "sparse+https://itsallbroken.com/index/".to_string(),
+                    RegistryConfig {
+                        path: Some("work/token".to_string()),
+                        backend: Some(BackendKind::Gopass),
+                        store_dir: Some(PathBuf::from("/srv/store")),
+                        store_key: Some("bananas@itsallbroken.com".to_string()),
+                        gnupg_home: Some(PathBuf::from("/srv/gnupg")),
+                        cache: Some(CachePolicy::Never),
+                        operations: vec![("publish", "work/publish-token".to_string())],
+                        token_pattern: Some("tok_.+".to_string()),
+                        verify: true,
+                    }
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let got = parse(
+            r#"
+            [aliases.urls]
+            "https://git.itsallbroken.com/index.git" = "sparse+https://itsallbroken.com/index/"
+
+            [aliases.prefixes]
+            "sparse+https://mirror.itsallbroken.com/" = "sparse+https://itsallbroken.com/"
+
+            [aliases.hosts]
+            "staging.itsallbroken.com" = "itsallbroken.com"
+            "#,
+        )
+        .expect("valid config");
+
+        assert_eq!(
+            got.aliases,
+            Aliases {
+                urls: vec![(
+                    "https://git.itsallbroken.com/index.git".to_string(),
+                    "sparse+https://itsallbroken.com/index/".to_string()
+                )],
+                prefixes: vec![(
+                    "sparse+https://mirror.itsallbroken.com/".to_string(),
+                    "sparse+https://itsallbroken.com/".to_string()
+                )],
+                hosts: vec![(
+                    "staging.itsallbroken.com".to_string(),
+                    "itsallbroken.com".to_string()
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse("").expect("valid config"), Config::default());
+    }
+
+    #[test]
+    fn test_registry_lookup() {
+        let got = parse(
+            r#"
+            [registries.bananas]
+            path = "by-name/"
+
+            [registries."sparse+https://itsallbroken.com/index"]
+            path = "by-url/"
+            "#,
+        )
+        .expect("valid config");
+
+        let path = |r: RegistryInfo<'_>| got.registry(&r).and_then(|v| v.path.clone());
+
+        // The name is matched in preference to the URL.
+        assert_eq!(
+            path(registry(
+                Some("bananas"),
+                "sparse+https://itsallbroken.com/index/"
+            )),
+            Some("by-name/".to_string())
+        );
+        assert_eq!(
+            path(registry(
+                Some("platanos"),
+                "sparse+https://itsallbroken.com/index/"
+            )),
+            Some("by-url/".to_string())
+        );
+        assert_eq!(
+            path(registry(None, "sparse+https://itsallbroken.com/index")),
+            Some("by-url/".to_string())
+        );
+        assert_eq!(path(registry(None, "sparse+https://crates.io/")), None);
+    }
+
+    #[test]
+    fn test_parse_unknown_keys() {
+        let got = parse("[registry]").expect_err("unknown key");
+        assert_eq!(
+            got.to_string(),
+            "unknown key 'registry' in the top level of config.toml \
+            (expected one of: registries, aliases)"
+        );
+
+        let got = parse("[aliases.paths]").expect_err("unknown key");
+        assert_eq!(
+            got.to_string(),
+            "unknown key 'paths' in [aliases] of config.toml (expected one of: \
+            urls, prefixes, hosts)"
+        );
+
+        let got = parse("[registries.bananas]\ndir = \"tokens/\"").expect_err("unknown key");
+        assert_eq!(
+            got.to_string(),
+            "unknown key 'dir' in [registries.bananas] of config.toml (expected one of: \
+            path, backend, store-dir, store-key, gnupghome, cache, operations, token-pattern, \
+            verify)"
+        );
+
+        let got = parse("[registries.bananas.operations]\nread = \"tokens/read\"")
+            .expect_err("unknown operation");
+        assert_eq!(
+            got.to_string(),
+            "unknown key 'read' in [registries.bananas.operations] of config.toml \
+            (expected one of: publish, yank, owners)"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_values() {
+        for (contents, want) in [
+            (
+                "registries = 42",
+                "invalid value for 'registries' in the top level of config.toml: expected a table",
+            ),
+            (
+                "[registries.bananas]\npath = 42",
+                "invalid value for 'path' in [registries.bananas] of config.toml: \
+                expected a string",
+            ),
+            (
+                "[registries.bananas]\npath = \"/root/token\"",
+                "invalid value for 'path' in [registries.bananas] of config.toml: \
+                absolute paths are not supported, specify a path relative to the password \
+                store root",
+            ),
+            (
+                "[registries.bananas]\nbackend = \"keepass\"",
+                "invalid value for 'backend' in [registries.bananas] of config.toml: \
+                unknown backend 'keepass' (expected one of: pass, gopass, passage)",
+            ),
+            (
+                "[registries.bananas]\nstore-dir = \"store\"",
+                "invalid value for 'store-dir' in [registries.bananas] of config.toml: \
+                directory must be absolute",
+            ),
+            (
+                "[registries.bananas]\ncache = \"forever\"",
+                "invalid value for 'cache' in [registries.bananas] of config.toml: \
+                unknown cache policy 'forever' (expected one of: expires, session, never)",
+            ),
+            (
+                "[aliases.urls]\n\"https://itsallbroken.com/index.git\" = \"\"",
+                "invalid value for 'https://itsallbroken.com/index.git' in [aliases.urls] of \
+                config.toml: expected a non-empty string",
+            ),
+            (
+                "[aliases.hosts]\n\"staging.itsallbroken.com\" = \"https://itsallbroken.com/\"",
+                "invalid value for 'staging.itsallbroken.com' in [aliases.hosts] of \
+                config.toml: expected a host name",
+            ),
+            (
+                "[registries.bananas]\nverify = \"yes\"",
+                "invalid value for 'verify' in [registries.bananas] of config.toml: \
+                expected a boolean",
+            ),
+        ] {
+            let got = parse(contents).expect_err(contents);
+            assert_eq!(got.to_string(), want, "{contents}");
+        }
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let got = Config::load_from(&dir.path().join("config.toml")).expect("missing is empty");
+        assert_eq!(got, Config::default());
+    }
+}
@@ -0,0 +1,616 @@
+//! Diagnostics for the environment the credential provider runs in.
+//!
+//! Failures in the password store setup (a missing `pass` binary, an
+//! uninitialised store, an expired GPG key, ...) otherwise only surface as an
+//! opaque error in the middle of a cargo command.
+
+use std::{
+    fmt::Display,
+    io::{ErrorKind, IsTerminal},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use time::OffsetDateTime;
+use toml_edit::{DocumentMut, Item};
+
+use crate::{args::ProviderArgs, import::cargo_home, pass::Backend};
+
+/// Keys expiring within this many days generate a warning.
+const KEY_EXPIRY_WARN_DAYS: i64 = 30;
+
+/// Collects (and prints) the outcome of each check.
+#[derive(Debug, Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    fn ok(&mut self, msg: impl Display) {
+        println!("ok:      {msg}");
+    }
+
+    fn warn(&mut self, msg: impl Display, fix: impl Display) {
+        println!("warning: {msg}");
+        println!("         fix: {fix}");
+    }
+
+    fn fail(&mut self, msg: impl Display, fix: impl Display) {
+        self.failed = true;
+        println!("error:   {msg}");
+        println!("         fix: {fix}");
+    }
+}
+
+/// Check each prerequisite of the provider configured by `args`, printing the
+/// results and any fixes to stdout.
+///
+/// Returns false if any check failed.
+pub(crate) fn run(args: &ProviderArgs<'_>) -> Result<bool, cargo_credential::Error> {
+    let backend = args.backend()?.into_backend();
+    let mut report = Report::default();
+
+    if check_backend(&mut report, backend.as_ref()) {
+        let store = args
+            .store_dir
+            .clone()
+            .unwrap_or_else(|| backend.store_dir());
+        if check_store(&mut report, &store) {
+            let recipients = match args.store_key {
+                Some(key) => store_key_recipients(&mut report, key),
+                None => check_recipients(&mut report, backend.as_ref(), &store, &token_dir(args)),
+            };
+
+            // Key checks only apply to GPG encrypted stores.
+            let gnupg_home = args.gnupg_home.as_deref();
+            if backend.extension() == ".gpg" && !recipients.is_empty() {
+                check_keys(&mut report, gnupg_home, &recipients);
+                check_agent(&mut report, gnupg_home);
+            }
+        }
+    }
+
+    check_cargo_config(&mut report);
+
+    Ok(!report.failed)
+}
+
+/// Check the backend CLI is installed.
+fn check_backend(report: &mut Report, backend: &dyn Backend) -> bool {
+    let mut cmd = backend.version();
+    let prog = cmd.get_program().to_string_lossy().to_string();
+
+    match cmd.output() {
+        Ok(v) if v.status.success() => {
+            let stdout = String::from_utf8_lossy(&v.stdout);
+            match version(&stdout) {
+                Some(version) => report.ok(format!("{prog} {version} found")),
+                None => report.ok(format!("{prog} found")),
+            }
+            true
+        }
+        Ok(v) => {
+            report.fail(
+                format!(
+                    "'{prog} version' failed: {}",
+                    String::from_utf8_lossy(&v.stderr).trim()
+                ),
+                format!("check your {prog} installation"),
+            );
+            false
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            report.fail(
+                format!("{prog} not found on PATH"),
+                format!("install {prog}, or select another backend with backend=<name>"),
+            );
+            false
+        }
+        Err(e) => {
+            report.fail(
+                format!("error executing {prog}: {e}"),
+                format!("check your {prog} installation"),
+            );
+            false
+        }
+    }
+}
+
+/// Check the store directory exists.
+fn check_store(report: &mut Report, store: &Path) -> bool {
+    if !store.is_dir() {
+        report.fail(
+            format!("password store {} does not exist", store.display()),
+            "initialise the store with 'pass init <gpg-id>', or set PASSWORD_STORE_DIR \
+            (or store-dir=<dir>) to the location of an existing store",
+        );
+        return false;
+    }
+
+    report.ok(format!("password store found at {}", store.display()));
+    true
+}
+
+/// Return the recipients of the configured `store-key=<gpg-id>`, which
+/// overrides the `.gpg-id` of the store.
+///
+/// As for `PASSWORD_STORE_KEY`, multiple recipients are separated by
+/// whitespace.
+fn store_key_recipients(report: &mut Report, key: &str) -> Vec<String> {
+    let recipients = key
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    report.ok(format!(
+        "tokens encrypted for {} (from store-key)",
+        recipients.join(", ")
+    ));
+    recipients
+}
+
+/// Check the encryption recipients of the token directory `dir` in `store`,
+/// returning them.
+fn check_recipients(
+    report: &mut Report,
+    backend: &dyn Backend,
+    store: &Path,
+    dir: &Path,
+) -> Vec<String> {
+    let name = backend.recipients_file();
+
+    let Some(file) = find_recipients_file(store, dir, name) else {
+        report.fail(
+            format!("no {name} file found for {}", dir.display()),
+            "initialise the store with 'pass init <gpg-id>'",
+        );
+        return vec![];
+    };
+
+    let recipients = match std::fs::read_to_string(&file) {
+        Ok(v) => parse_recipients(&v),
+        Err(e) => {
+            report.fail(
+                format!("error reading {}: {e}", file.display()),
+                format!("check the permissions of {}", file.display()),
+            );
+            return vec![];
+        }
+    };
+
+    if recipients.is_empty() {
+        report.fail(
+            format!("{} lists no recipients", file.display()),
+            "initialise the store with 'pass init <gpg-id>'",
+        );
+        return vec![];
+    }
+
+    report.ok(format!(
+        "tokens in {} encrypted for {} (from {})",
+        dir.display(),
+        recipients.join(", "),
+        file.display()
+    ));
+
+    recipients
+}
+
+/// Check a usable public key exists for every recipient, and a secret key for
+/// at least one.
+fn check_keys(report: &mut Report, gnupg_home: Option<&Path>, recipients: &[String]) {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let mut has_secret = false;
+
+    for r in recipients {
+        let Some(keys) = list_keys(report, gnupg_home, "--list-keys", r) else {
+            return;
+        };
+
+        let Some(key) = keys.first() else {
+            report.fail(
+                format!("no public key found for recipient {r}"),
+                format!("import the key for {r} with 'gpg --import'"),
+            );
+            continue;
+        };
+
+        match key.validity {
+            'e' => report.fail(
+                format!("the key for recipient {r} has expired"),
+                format!(
+                    "extend the key expiry with 'gpg --quick-set-expire', then run 'pass init {r}'"
+                ),
+            ),
+            'r' => report.fail(
+                format!("the key for recipient {r} has been revoked"),
+                "re-initialise the store for a new key with 'pass init <gpg-id>'",
+            ),
+            _ => match key.expires {
+                Some(ts) if ts - now < KEY_EXPIRY_WARN_DAYS * 24 * 60 * 60 => report.warn(
+                    format!(
+                        "the key for recipient {r} expires in {} days",
+                        (ts - now) / (24 * 60 * 60)
+                    ),
+                    "extend the key expiry with 'gpg --quick-set-expire'",
+                ),
+                _ => report.ok(format!("public key found for recipient {r}")),
+            },
+        }
+
+        let Some(secret) = list_keys(report, gnupg_home, "--list-secret-keys", r) else {
+            return;
+        };
+        has_secret |= !secret.is_empty();
+    }
+
+    if !has_secret {
+        report.fail(
+            "no secret key available to decrypt tokens",
+            "import the secret key for one of the recipients with 'gpg --import'",
+        );
+    }
+}
+
+/// Check `gpg-agent` is running (or can be started), and can prompt for a
+/// passphrase.
+fn check_agent(report: &mut Report, gnupg_home: Option<&Path>) {
+    match gpg_command("gpg-connect-agent", gnupg_home)
+        .arg("/bye")
+        .output()
+    {
+        Ok(v) if v.status.success() => report.ok("gpg-agent is running"),
+        Ok(v) => report.fail(
+            format!(
+                "gpg-agent could not be started: {}",
+                String::from_utf8_lossy(&v.stderr).trim()
+            ),
+            "check your gpg installation, and that $GNUPGHOME is writable",
+        ),
+        Err(e) => report.fail(
+            format!("error executing gpg-connect-agent: {e}"),
+            "check your gpg installation",
+        ),
+    }
+
+    if std::env::var_os("GPG_TTY").is_some() {
+        report.ok("GPG_TTY is set");
+    } else if std::io::stdin().is_terminal() {
+        report.warn(
+            "GPG_TTY is not set, so pinentry may be unable to prompt for your passphrase",
+            "add 'export GPG_TTY=$(tty)' to your shell profile",
+        );
+    }
+}
+
+/// Check cargo is configured to use this credential provider.
+fn check_cargo_config(report: &mut Report) {
+    let files = cargo_config_files();
+
+    for file in &files {
+        let Ok(content) = std::fs::read_to_string(file) else {
+            continue;
+        };
+
+        match content.parse::<DocumentMut>() {
+            Ok(doc) if uses_provider(&doc) => {
+                report.ok(format!(
+                    "cargo is configured to use this provider in {}",
+                    file.display()
+                ));
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => report.warn(
+                format!("error parsing {}: {e}", file.display()),
+                "fix the syntax error in the cargo config file",
+            ),
+        }
+    }
+
+    let config = files
+        .last()
+        .cloned()
+        .or_else(|| cargo_home().map(|v| v.join("config.toml")))
+        .unwrap_or_else(|| PathBuf::from("~/.cargo/config.toml"));
+
+    report.warn(
+        "cargo is not configured to use cargo-credential-pass",
+        format!(
+            "add 'global-credential-providers = [\"cargo-credential-pass\"]' to the \
+            [registry] table in {}",
+            config.display()
+        ),
+    );
+}
+
+/// Return the directory tokens are stored in, relative to the store root.
+fn token_dir(args: &ProviderArgs<'_>) -> PathBuf {
+    match args.path {
+        Some(v) if v.ends_with('/') => PathBuf::from(v),
+        Some(v) => Path::new(v)
+            .parent()
+            .map(ToOwned::to_owned)
+            .unwrap_or_default(),
+        None => PathBuf::from("cargo-registry"),
+    }
+}
+
+/// Return the cargo config files that may configure credential providers, in
+/// order of precedence.
+fn cargo_config_files() -> Vec<PathBuf> {
+    let mut dirs = std::env::current_dir()
+        .map(|v| v.ancestors().map(|v| v.join(".cargo")).collect::<Vec<_>>())
+        .unwrap_or_default();
+    dirs.extend(cargo_home());
+
+    dirs.into_iter()
+        .flat_map(|v| [v.join("config.toml"), v.join("config")])
+        .filter(|v| v.is_file())
+        .collect()
+}
+
+/// Run `gpg <cmd>` for `recipient`, returning the keys found.
+///
+/// Returns [`None`] (after reporting the failure) if `gpg` cannot be executed.
+fn list_keys(
+    report: &mut Report,
+    gnupg_home: Option<&Path>,
+    cmd: &str,
+    recipient: &str,
+) -> Option<Vec<Key>> {
+    let output = gpg_command("gpg", gnupg_home)
+        .args(["--batch", "--with-colons", cmd, "--", recipient])
+        .output();
+
+    match output {
+        Ok(v) => Some(parse_keys(&String::from_utf8_lossy(&v.stdout))),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            report.fail("gpg not found on PATH", "install gpg");
+            None
+        }
+        Err(e) => {
+            report.fail(
+                format!("error executing gpg: {e}"),
+                "check your gpg installation",
+            );
+            None
+        }
+    }
+}
+
+/// Construct a [`Command`] for the GnuPG program `prog`, using the GnuPG home
+/// directory `gnupg_home` if configured.
+fn gpg_command(prog: &str, gnupg_home: Option<&Path>) -> Command {
+    let mut cmd = Command::new(prog);
+    if let Some(dir) = gnupg_home {
+        cmd.env("GNUPGHOME", dir);
+    }
+    cmd
+}
+
+/// Extract the version number from the output of a `version` command.
+fn version(stdout: &str) -> Option<&str> {
+    stdout
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .find(|v| {
+            let v = v.strip_prefix('v').unwrap_or(v);
+            v.starts_with(|c: char| c.is_ascii_digit()) && v.contains('.')
+        })
+}
+
+/// Search `dir` and its parents (up to the `store` root) for the recipients
+/// file `name`, as `pass` does.
+fn find_recipients_file(store: &Path, dir: &Path, name: &str) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|v| store.join(v).join(name))
+        .find(|v| v.is_file())
+}
+
+/// Parse the recipients listed in a recipients file, ignoring blank lines and
+/// comments.
+fn parse_recipients(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|v| v.split('#').next().unwrap_or_default().trim())
+        .filter(|v| !v.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// A GPG (primary) key.
+#[derive(Debug, PartialEq, Eq)]
+struct Key {
+    /// The validity field - `e` for expired, `r` for revoked.
+    validity: char,
+
+    /// The expiry time as a unix timestamp, if any.
+    expires: Option<i64>,
+}
+
+/// Parse the primary keys in `gpg --with-colons` key listing output.
+fn parse_keys(output: &str) -> Vec<Key> {
+    output
+        .lines()
+        .map(|v| v.split(':').collect::<Vec<_>>())
+        .filter(|v| matches!(v.first(), Some(&"pub") | Some(&"sec")))
+        .map(|v| Key {
+            validity: v.get(1).and_then(|v| v.chars().next()).unwrap_or('-'),
+            expires: v.get(6).and_then(|v| v.parse().ok()),
+        })
+        .collect()
+}
+
+/// Returns true if the cargo config `doc` configures this credential provider,
+/// globally or for any registry.
+fn uses_provider(doc: &DocumentMut) -> bool {
+    let aliases = doc.get("credential-alias").and_then(Item::as_table_like);
+
+    // A provider is either a string, or an array of strings, optionally
+    // naming a credential-alias.
+    let is_provider = |v: &Item| {
+        let v = match v.as_array() {
+            Some(v) => v.get(0).and_then(|v| v.as_str()),
+            None => v.as_str(),
+        };
+        let Some(v) = v.and_then(|v| v.split_whitespace().next()) else {
+            return false;
+        };
+
+        let v = match aliases.and_then(|a| a.get(v)) {
+            Some(alias) => match alias.as_array() {
+                Some(a) => a.get(0).and_then(|v| v.as_str()),
+                None => alias.as_str().and_then(|v| v.split_whitespace().next()),
+            },
+            None => Some(v),
+        };
+
+        v.and_then(|v| Path::new(v).file_name())
+            .and_then(|v| v.to_str())
+            .is_some_and(|v| v.starts_with("cargo-credential-pass"))
+    };
+
+    let global = doc
+        .get("registry")
+        .and_then(|v| v.get("global-credential-providers"))
+        .and_then(Item::as_array)
+        .is_some_and(|v| v.iter().any(|v| is_provider(&Item::Value(v.clone()))));
+
+    let registry = doc
+        .get("registry")
+        .and_then(|v| v.get("credential-provider"))
+        .is_some_and(is_provider);
+
+    let registries = doc
+        .get("registries")
+        .and_then(Item::as_table_like)
+        .is_some_and(|v| {
+            v.iter()
+                .filter_map(|(_, v)| v.get("credential-provider"))
+                .any(is_provider)
+        });
+
+    global || registry || registries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(
+            version("============================================\n= pass: the standard unix password manager =\n=                  v1.7.4                  =\n"),
+            Some("v1.7.4")
+        );
+        assert_eq!(
+            version("gopass 1.15.14 go1.23.4 linux amd64"),
+            Some("1.15.14")
+        );
+        assert_eq!(version("bananas"), None);
+    }
+
+    #[test]
+    fn test_parse_recipients() {
+        let got = parse_recipients("# comment\nbananas@itsallbroken.com\n\n  ABCDEF # key\n");
+        assert_eq!(got, ["bananas@itsallbroken.com", "ABCDEF"]);
+    }
+
+    #[test]
+    fn test_store_key_recipients() {
+        let got = store_key_recipients(
+            &mut Report::default(),
+            "bananas@itsallbroken.com  ABCDEF\tplatanos@itsallbroken.com",
+        );
+        assert_eq!(
+            got,
+            [
+                "bananas@itsallbroken.com",
+                "ABCDEF",
+                "platanos@itsallbroken.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_recipients_file() {
+        let store = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(store.path().join("cargo-registry/nested")).unwrap();
+
+        assert_eq!(
+            find_recipients_file(store.path(), Path::new("cargo-registry/nested"), ".gpg-id"),
+            None
+        );
+
+        std::fs::write(store.path().join(".gpg-id"), "root").unwrap();
+        assert_eq!(
+            find_recipients_file(store.path(), Path::new("cargo-registry/nested"), ".gpg-id"),
+            Some(store.path().join(".gpg-id"))
+        );
+
+        std::fs::write(store.path().join("cargo-registry/.gpg-id"), "cargo").unwrap();
+        assert_eq!(
+            find_recipients_file(store.path(), Path::new("cargo-registry/nested"), ".gpg-id"),
+            Some(store.path().join("cargo-registry/.gpg-id"))
+        );
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let got = parse_keys(
+            "tru::1:1733043600:0:3:1:5\n\
+            pub:u:255:22:51ADDED244EFD2C5:1733043600:1764579600::u:::scESC:::::ed25519:::0:\n\
+            fpr:::::::::17B00D086F53501E5B75BD1051ADDED244EFD2C5:\n\
+            uid:u::::1733043600::0::Cargo Test Key <bananas@itsallbroken.com>::::::::::0:\n\
+            sub:u:255:18:A4E8B3F0C1D2E3F4:1733043600::::::e:::::cv25519::\n\
+            pub:e:255:22:0123456789ABCDEF:1600000000:1700000000::u:::scESC:::::ed25519:::0:\n",
+        );
+
+        assert_eq!(
+            got,
+            [
+                Key {
+                    validity: 'u',
+                    expires: Some(1764579600)
+                },
+                Key {
+                    validity: 'e',
+                    expires: Some(1700000000)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_dir() {
+        let dir = |args: &[&str]| token_dir(&ProviderArgs::parse(args).expect("valid args"));
+
+        assert_eq!(dir(&[]), PathBuf::from("cargo-registry"));
+        assert_eq!(dir(&["tokens/"]), PathBuf::from("tokens/"));
+        assert_eq!(dir(&["tokens/bananas"]), PathBuf::from("tokens"));
+        assert_eq!(dir(&["bananas"]), PathBuf::from(""));
+    }
+
+    #[test]
+    fn test_uses_provider() {
+        let uses = |s: &str| uses_provider(&s.parse().expect("valid toml"));
+
+        assert!(uses(
+            "[registry]\nglobal-credential-providers = [\"cargo:token\", \"cargo-credential-pass --direct-gpg\"]\n"
+        ));
+        assert!(uses(
+            "[registry]\nglobal-credential-providers = [\"/usr/local/bin/cargo-credential-pass\"]\n"
+        ));
+        assert!(uses(
+            "[registries.bananas]\ncredential-provider = [\"cargo-credential-pass\", \"tokens/\"]\n"
+        ));
+        assert!(uses(
+            "[credential-alias]\npass = \"cargo-credential-pass --backend=gopass\"\n\n\
+            [registry]\nglobal-credential-providers = [\"pass\"]\n"
+        ));
+
+        assert!(!uses(""));
+        assert!(!uses(
+            "[registry]\nglobal-credential-providers = [\"cargo:token\"]\n"
+        ));
+    }
+}
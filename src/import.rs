@@ -0,0 +1,270 @@
+//! Migration of plaintext tokens from cargo's `credentials.toml` into the
+//! password store.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use cargo_credential::Secret;
+use thiserror::Error;
+use toml_edit::{DocumentMut, Item};
+
+use crate::registry::CRATES_IO;
+
+/// Failures reading or rewriting the cargo credentials file.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    /// The credentials file could not be read.
+    #[error("error reading {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    /// The credentials file is not valid TOML.
+    #[error("error parsing {0}: {1}")]
+    Parse(PathBuf, toml_edit::TomlError),
+
+    /// The credentials file (or its backup) could not be written.
+    #[error("error writing {0}: {1}")]
+    Write(PathBuf, std::io::Error),
+
+    /// A `token` entry is not a string.
+    #[error("token for registry '{0}' is not a string")]
+    InvalidToken(String),
+}
+
+/// A plaintext registry token read from the credentials file.
+#[derive(Debug)]
+pub(crate) struct Token {
+    /// The name of the registry the token is for.
+    pub(crate) registry: String,
+    pub(crate) token: Secret<String>,
+}
+
+/// A cargo `credentials.toml` file.
+#[derive(Debug)]
+pub(crate) struct Credentials {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl Credentials {
+    /// Return the path of the credentials file in `$CARGO_HOME` (or
+    /// `~/.cargo`), preferring `credentials.toml` over the legacy
+    /// extension-less `credentials` file if both exist.
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        let home = cargo_home()?;
+
+        let legacy = home.join("credentials");
+        if !home.join("credentials.toml").exists() && legacy.exists() {
+            return Some(legacy);
+        }
+
+        Some(home.join("credentials.toml"))
+    }
+
+    /// Read and parse the credentials file at `path`.
+    ///
+    /// A missing file is treated as an empty credentials file.
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::Read(path.to_path_buf(), e)),
+        };
+
+        let doc = content
+            .parse::<DocumentMut>()
+            .map_err(|e| Error::Parse(path.to_path_buf(), e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            doc,
+        })
+    }
+
+    /// The path of this credentials file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the tokens in the `[registry]` (crates.io) and
+    /// `[registries.<name>]` tables.
+    pub(crate) fn tokens(&self) -> Result<Vec<Token>, Error> {
+        let mut out = Vec::new();
+
+        if let Some(v) = self.doc.get("registry").and_then(|v| v.get("token")) {
+            out.push(token(CRATES_IO, v)?);
+        }
+
+        let registries = self.doc.get("registries").and_then(Item::as_table_like);
+        for (name, table) in registries.into_iter().flat_map(|v| v.iter()) {
+            if let Some(v) = table.get("token") {
+                out.push(token(name, v)?);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Remove the tokens for all `registries`, dropping any tables left empty.
+    ///
+    /// Other keys (such as asymmetric `secret-key` entries) are preserved.
+    pub(crate) fn remove_tokens<'a>(&mut self, registries: impl IntoIterator<Item = &'a str>) {
+        for name in registries {
+            if name == CRATES_IO {
+                remove_token(self.doc.as_item_mut(), "registry");
+                continue;
+            }
+
+            if let Some(v) = self.doc.get_mut("registries") {
+                remove_token(v, name);
+            }
+        }
+
+        let empty = self
+            .doc
+            .get("registries")
+            .and_then(Item::as_table_like)
+            .is_some_and(|v| v.is_empty());
+        if empty {
+            self.doc.remove("registries");
+        }
+    }
+
+    /// Write the credentials file, first copying the original file to a
+    /// `.bak` backup alongside it.
+    pub(crate) fn save_with_backup(&self) -> Result<PathBuf, Error> {
+        let mut backup = self.path.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+
+        std::fs::copy(&self.path, &backup).map_err(|e| Error::Write(backup.clone(), e))?;
+        std::fs::write(&self.path, self.doc.to_string())
+            .map_err(|e| Error::Write(self.path.clone(), e))?;
+
+        Ok(backup)
+    }
+}
+
+/// Return the cargo home directory - `$CARGO_HOME`, or `~/.cargo` if unset.
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|v| PathBuf::from(v).join(".cargo")))
+}
+
+/// Construct a [`Token`] for `registry` from the `token` value `v`.
+fn token(registry: &str, v: &Item) -> Result<Token, Error> {
+    let token = v
+        .as_str()
+        .ok_or_else(|| Error::InvalidToken(registry.to_string()))?;
+
+    Ok(Token {
+        registry: registry.to_string(),
+        token: Secret::from(token.to_string()),
+    })
+}
+
+/// Remove the `token` key from the `name` table in `parent`, removing the
+/// table if it is then empty.
+fn remove_token(parent: &mut Item, name: &str) {
+    let Some(parent) = parent.as_table_like_mut() else {
+        return;
+    };
+
+    let Some(table) = parent.get_mut(name).and_then(Item::as_table_like_mut) else {
+        return;
+    };
+
+    table.remove("token");
+    if table.is_empty() {
+        parent.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Credentials {
+        Credentials {
+            path: PathBuf::from("credentials.toml"),
+            doc: s.parse().expect("valid toml"),
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let c = parse(
+            r#"
+            [registry]
+            token = "bananas"
+
+            [registries.platanos]
+            token = "platanos"
+
+            [registries.keys]
+            secret-key = "k3.secret.bananas"
+            "#,
+        );
+
+        let got = c
+            .tokens()
+            .expect("valid tokens")
+            .into_iter()
+            .map(|v| (v.registry, v.token.expose()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            got,
+            [
+                ("crates-io".to_string(), "bananas".to_string()),
+                ("platanos".to_string(), "platanos".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_empty() {
+        assert!(parse("").tokens().expect("valid").is_empty());
+    }
+
+    #[test]
+    fn test_tokens_invalid() {
+        let c = parse("[registries.bananas]\ntoken = 42\n");
+        assert!(matches!(c.tokens(), Err(Error::InvalidToken(v)) if v == "bananas"));
+    }
+
+    #[test]
+    fn test_remove_tokens() {
+        let mut c = parse(
+            r#"[registry]
+token = "bananas"
+
+[registries.platanos]
+token = "platanos"
+
+[registries.keys]
+token = "keys"
+secret-key = "k3.secret.bananas"
+"#,
+        );
+
+        c.remove_tokens(["crates-io", "platanos", "keys"]);
+
+        assert_eq!(
+            c.doc.to_string(),
+            r#"
+[registries.keys]
+secret-key = "k3.secret.bananas"
+"#
+        );
+    }
+
+    #[test]
+    fn test_remove_all_tokens() {
+        let mut c = parse("[registries.platanos]\ntoken = \"platanos\"\n");
+        c.remove_tokens(["platanos"]);
+        assert_eq!(c.doc.to_string(), "");
+    }
+}
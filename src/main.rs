@@ -14,12 +14,35 @@
 //   License for the specific language governing permissions and limitations
 //   under the License.
 
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
 
-use cargo_credential::{Action, CacheControl, Credential, CredentialResponse, RegistryInfo};
-use pass::{PassKeychain, PassPath, PassPathBuilder};
+use args::{CachePolicy, ProviderArgs};
+use cargo_credential::{
+    Action, CacheControl, Credential, CredentialResponse, RegistryInfo, Secret,
+};
+use pass::{
+    crate_name, operation_name, Entry, PassKeychain, PassPath, PassPathBuilder, OPERATIONS,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
+mod alias;
+mod args;
+mod cli;
+mod config;
+mod doctor;
+mod import;
+mod paseto;
 mod pass;
+mod registry;
+mod token;
+#[cfg(feature = "verify")]
+mod verify;
 
 /// Convert [`pass::Error`] instances into RPC error responses.
 impl From<pass::Error> for cargo_credential::Error {
@@ -27,13 +50,58 @@ impl From<pass::Error> for cargo_credential::Error {
         use pass::Error;
 
         match v {
-            Error::Exec(_) | Error::ExecNonZero { .. } | Error::NonUtf8Password(_) => {
-                cargo_credential::Error::Other(v.into())
-            }
+            Error::Exec(_)
+            | Error::ExecNonZero { .. }
+            | Error::ExecInteractive(_)
+            | Error::NoTerminal(_)
+            | Error::Prompt(_)
+            | Error::Timeout(_)
+            | Error::InvalidExpiry(_)
+            | Error::ReadStore(_)
+            | Error::InvalidPath(_)
+            | Error::InvalidTemplate { .. }
+            | Error::NonUtf8Password(_) => cargo_credential::Error::Other(v.into()),
+            Error::NotFound(_) => cargo_credential::Error::NotFound,
         }
     }
 }
 
+/// Convert [`paseto::Error`] instances into RPC error responses.
+impl From<paseto::Error> for cargo_credential::Error {
+    fn from(v: paseto::Error) -> Self {
+        cargo_credential::Error::Other(v.into())
+    }
+}
+
+/// Convert [`import::Error`] instances into RPC error responses.
+impl From<import::Error> for cargo_credential::Error {
+    fn from(v: import::Error) -> Self {
+        cargo_credential::Error::Other(v.into())
+    }
+}
+
+/// Convert [`config::Error`] instances into RPC error responses.
+impl From<config::Error> for cargo_credential::Error {
+    fn from(v: config::Error) -> Self {
+        cargo_credential::Error::Other(v.into())
+    }
+}
+
+/// Convert [`token::Error`] instances into RPC error responses.
+impl From<token::Error> for cargo_credential::Error {
+    fn from(v: token::Error) -> Self {
+        cargo_credential::Error::Other(v.into())
+    }
+}
+
+/// Convert [`verify::Error`] instances into RPC error responses.
+#[cfg(feature = "verify")]
+impl From<verify::Error> for cargo_credential::Error {
+    fn from(v: verify::Error) -> Self {
+        cargo_credential::Error::Other(v.into())
+    }
+}
+
 /// A request dispatcher for [`cargo_credential`].
 ///
 /// A thin layer to translate [`cargo_credential::Action`] ops into
@@ -47,31 +115,140 @@ impl Credential for Dispatch {
         action: &Action<'_>,
         args: &[&str],
     ) -> Result<CredentialResponse, cargo_credential::Error> {
-        let path = path_from_args(args, registry)?;
-        let keychain = PassKeychain::default();
+        let config = config::Config::load()?;
+
+        // Resolve any alias of the index URL before the registry config and
+        // token paths are looked up, so that all URLs of a registry share one
+        // token. The registry itself is always contacted at the URL cargo
+        // provided.
+        let index_url = config.aliases().resolve(registry.index_url);
+        let canonical = RegistryInfo {
+            index_url: &index_url,
+            name: registry.name,
+            headers: vec![],
+        };
+
+        let args = ProviderArgs::parse(args)?.with_config(config.registry(&canonical));
+        let keychain = args.keychain()?;
 
         match action {
             // Prompt for a token (or use the one provided) and store it into
-            // the password store.
+            // the password store, or open the entry in an editor if
+            // configured.
             Action::Login(opts) => {
-                match &opts.token {
-                    Some(token) => keychain.upsert_token(&path, token),
-                    None => keychain.edit_token(&path),
-                }?;
+                let path = path_from_args(&args, &canonical, args.operation, None)?;
+
+                if args.paseto {
+                    login_paseto(&keychain, &path, opts.token.as_ref(), args.key_subject)?;
+                    return Ok(CredentialResponse::Login);
+                }
+
+                let raw = match &opts.token {
+                    Some(token) => token.as_ref().map(|v| v.to_string()),
+                    None if args.editor => {
+                        if let Some(url) = opts.login_url {
+                            eprintln!("please paste the token found on {url} into the editor");
+                            open_login_url(url, args.open_url);
+                        }
+                        keychain.edit_token(&path)?;
+                        return Ok(CredentialResponse::Login);
+                    }
+                    None => {
+                        let name = registry.name.unwrap_or(registry.index_url);
+                        if let Some(url) = opts.login_url {
+                            eprintln!("please paste the token found on {url} below");
+                            open_login_url(url, args.open_url);
+                        }
+                        pass::prompt_secret(&format!("token for {name}: "))?
+                    }
+                };
+
+                // Reject (or clean up) a mis-pasted token before it is stored.
+                let pattern = args.token_pattern()?;
+                let token = token::validate(raw.as_deref().expose(), &canonical, pattern.as_ref())?;
+
+                // Check the registry accepts the token before it replaces an
+                // existing (working) token.
+                if args.verify {
+                    #[cfg(feature = "verify")]
+                    verify::verify(registry.index_url, token, args.timeout()?)?;
+
+                    #[cfg(not(feature = "verify"))]
+                    return Err("token verification is not supported by this build - \
+                        reinstall with the 'verify' feature enabled"
+                        .into());
+                }
+
+                keychain.upsert_token(&path, &Secret::from(token))?;
 
                 Ok(CredentialResponse::Login)
             }
 
             // Return a token for a specific registry, if one exists.
-            Action::Get(_opts) => Ok(CredentialResponse::Get {
-                token: keychain.read_token(&path)?,
-                cache: CacheControl::Session,
-                operation_independent: true,
-            }),
+            Action::Get(op) => {
+                // Use an operation-specific token in preference to the generic
+                // registry token, if one exists.
+                let op_entry = match operation_name(op) {
+                    Some(name) => {
+                        let candidates =
+                            candidate_paths(&args, &canonical, Some(name), crate_name(op))?;
+                        find_entry(&keychain, &candidates, args.debug())?
+                    }
+                    None => None,
+                };
+
+                // The generic token can only be used for all operations if
+                // there are no operation-specific tokens cargo should ask for
+                // instead.
+                let (path, entry, operation_independent) = match op_entry {
+                    Some((path, entry)) => (path, entry, false),
+                    None => {
+                        let candidates = candidate_paths(&args, &canonical, None, crate_name(op))?;
+                        let Some((path, entry)) = find_entry(&keychain, &candidates, args.debug())?
+                        else {
+                            return Err(cargo_credential::Error::NotFound);
+                        };
+                        let independent = !is_operation_templated(&args)
+                            && !has_operation_tokens(&keychain, &args, &canonical, crate_name(op))?;
+                        (path, entry, independent)
+                    }
+                };
+
+                let now = OffsetDateTime::now_utc();
+                let cache = cache_control(
+                    &path,
+                    &entry,
+                    args.cache.unwrap_or_default(),
+                    args.expiry_warning()?,
+                    now,
+                )?;
+
+                // Mint a signed token if the entry holds an asymmetric secret
+                // key.
+                if paseto::is_secret_key(entry.token().expose()) {
+                    let key = paseto::SecretKey::from_paserk(entry.token().expose())?;
+                    let token =
+                        paseto::token(&key, entry.get("subject"), op, registry.index_url, now)?;
+
+                    return Ok(CredentialResponse::Get {
+                        token,
+                        cache: paseto::cache_control(op, cache, now),
+                        operation_independent: false,
+                    });
+                }
+
+                Ok(CredentialResponse::Get {
+                    token: entry.into_token(),
+                    cache,
+                    operation_independent,
+                })
+            }
 
-            // Destroy the token in the password store.
+            // Destroy the token in the password store, including any other
+            // candidate it would otherwise be read from.
             Action::Logout => {
-                keychain.delete_token(&path)?;
+                let candidates = candidate_paths(&args, &canonical, args.operation, None)?;
+                delete_candidates(&keychain, &candidates)?;
                 Ok(CredentialResponse::Logout)
             }
             _ => Err(cargo_credential::Error::OperationNotSupported),
@@ -79,36 +256,288 @@ impl Credential for Dispatch {
     }
 }
 
-/// Parse (potentially empty) `args` to construct a [`PassPath`].
+/// Open the registry `url` to mint a token with in a browser, if `open` is
+/// set.
+///
+/// Failing to open the URL is not fatal, as it has already been printed.
+fn open_login_url(url: &str, open: bool) {
+    if !open {
+        return;
+    }
+
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    let res = std::process::Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    if let Err(e) = res {
+        eprintln!("warning: failed to open {url} with {opener}: {e}");
+    }
+}
+
+/// Store an asymmetric (PASETO) secret key at `path`, printing the public key
+/// to stderr so it can be registered with the registry.
+///
+/// If a `token` is provided it must be a PASERK encoded secret key, otherwise a
+/// new key is generated. The key `subject` is stored in the entry metadata.
+fn login_paseto(
+    keychain: &PassKeychain,
+    path: &PassPath,
+    token: Option<&Secret<&str>>,
+    subject: Option<&str>,
+) -> Result<(), cargo_credential::Error> {
+    let key = match token {
+        Some(v) => paseto::SecretKey::from_paserk(v.as_deref().expose())?,
+        None => paseto::SecretKey::generate(),
+    };
+
+    let metadata = subject.map(|v| ("subject", v));
+    keychain.upsert_token_with(path, &key.to_paserk().as_deref(), metadata.as_slice())?;
+
+    eprintln!("{}", key.public_paserk());
+
+    Ok(())
+}
+
+/// Returns true if any operation-specific tokens exist for `registry` (and
+/// `crate_name`, if the token path is templated by crate).
+fn has_operation_tokens(
+    keychain: &PassKeychain,
+    args: &ProviderArgs<'_>,
+    registry: &RegistryInfo<'_>,
+    crate_name: Option<&str>,
+) -> Result<bool, cargo_credential::Error> {
+    for op in OPERATIONS {
+        let candidates = candidate_paths(args, registry, Some(op), crate_name)?;
+        if candidates.iter().any(|v| keychain.contains(v)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Returns true if the token paths in `args` are templated by operation or
+/// crate, in which case the token returned for one operation says nothing
+/// about the token for another.
+///
+/// For a read, `{crate}` and `{operation}` expand to `default`, so the generic
+/// token found must not be reused for other operations.
+fn is_operation_templated(args: &ProviderArgs<'_>) -> bool {
+    args.path
+        .into_iter()
+        .chain(args.operation_paths.iter().map(|(_, v)| v.as_str()))
+        .any(|v| v.contains("{crate}") || v.contains("{operation}"))
+}
+
+/// Return the paths a token for `registry` may be stored at, in order of
+/// preference.
+///
+///   1. The canonical path from [`path_from_args()`], which new tokens are
+///      always stored at.
+///   2. The path derived from the index URL alone, for a registry referred to
+///      by name here and only by URL elsewhere.
+///   3. The legacy path of the index URL (see [`legacy_path_from_args()`]).
+///
+/// Paths that resolve to the same entry are only included once.
+fn candidate_paths(
+    args: &ProviderArgs<'_>,
+    registry: &RegistryInfo<'_>,
+    operation: Option<&'static str>,
+    crate_name: Option<&str>,
+) -> Result<Vec<PassPath>, cargo_credential::Error> {
+    let mut out = vec![path_from_args(args, registry, operation, crate_name)?];
+
+    // A registry named on the command line has no index URL.
+    if !registry.index_url.is_empty() {
+        let unnamed = RegistryInfo {
+            index_url: registry.index_url,
+            name: None,
+            headers: vec![],
+        };
+
+        out.push(path_from_args(args, &unnamed, operation, crate_name)?);
+        out.extend(legacy_path_from_args(args, &unnamed, operation)?);
+    }
+
+    let mut seen = HashSet::new();
+    out.retain(|v| seen.insert(v.to_string()));
+
+    Ok(out)
+}
+
+/// Read the entry at the first of `candidates` that exists, returning [`None`]
+/// if there are none.
+///
+/// If `debug` is set, the candidates tried and the one used are printed to
+/// stderr.
+fn find_entry(
+    keychain: &PassKeychain,
+    candidates: &[PassPath],
+    debug: bool,
+) -> Result<Option<(PassPath, Entry)>, pass::Error> {
+    for (i, path) in candidates.iter().enumerate() {
+        // The canonical path is always read by the backend, which reports
+        // its own failures - later candidates are only read if they may
+        // exist, to avoid running the backend for each of them.
+        let entry = match i == 0 || keychain.may_contain(path) {
+            true => keychain.read_entry(path),
+            false => Err(pass::Error::NotFound(path.clone())),
+        };
+
+        match entry {
+            Ok(entry) => {
+                if debug {
+                    eprintln!("debug: using token in {path}");
+                }
+                return Ok(Some((path.clone(), entry)));
+            }
+            Err(pass::Error::NotFound(_)) => {
+                if debug {
+                    eprintln!("debug: no token in {path}");
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Delete the token at each of `candidates` that exists, returning the paths
+/// deleted.
+///
+/// Returns [`pass::Error::NotFound`] for the first (canonical) candidate if
+/// none exist.
+fn delete_candidates(
+    keychain: &PassKeychain,
+    candidates: &[PassPath],
+) -> Result<Vec<PassPath>, pass::Error> {
+    let stored = candidates
+        .iter()
+        .filter(|v| keychain.contains(v))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if stored.is_empty() {
+        if let Some(path) = candidates.first() {
+            keychain.delete_token(path)?;
+        }
+    }
+
+    for path in &stored {
+        keychain.delete_token(path)?;
+    }
+
+    Ok(stored)
+}
+
+/// Derive the [`CacheControl`] for the token in `entry` from the cache
+/// `policy` and its expiry time.
+///
+/// With the default [`CachePolicy::Expires`] policy tokens without an expiry
+/// time are cached for the session. Tokens that have expired at `now` are never
+/// returned, regardless of the policy. A warning is printed to stderr if the
+/// token expires within `warn_within` of `now`.
+fn cache_control(
+    path: &PassPath,
+    entry: &Entry,
+    policy: CachePolicy,
+    warn_within: Duration,
+    now: OffsetDateTime,
+) -> Result<CacheControl, cargo_credential::Error> {
+    let fallback = match policy {
+        CachePolicy::Never => CacheControl::Never,
+        CachePolicy::Expires | CachePolicy::Session => CacheControl::Session,
+    };
+
+    let Some(expiration) = entry.expires()? else {
+        return Ok(fallback);
+    };
+
+    let ts = expiration.format(&Rfc3339).map_err(Box::new)?;
+
+    if expiration <= now {
+        return Err(format!(
+            "token in {path} expired at {ts}, use 'cargo login' to store a new token"
+        )
+        .into());
+    }
+
+    let remaining = expiration - now;
+    if remaining <= warn_within {
+        let remaining = match remaining.whole_hours() {
+            h if h >= 48 => format!("{} days", h / 24),
+            h if h >= 1 => format!("{h} hours"),
+            _ => format!("{} minutes", remaining.whole_minutes()),
+        };
+        eprintln!("warning: token in {path} expires in {remaining} (at {ts})");
+    }
+
+    match policy {
+        CachePolicy::Expires => Ok(CacheControl::Expires { expiration }),
+        CachePolicy::Session | CachePolicy::Never => Ok(fallback),
+    }
+}
+
+/// Use the (optional) path in `args` to construct a [`PassPath`].
+///
+///   1. If no path is given, return a [`PassPath`] derived from `registry`.
+///   2. If the path starts with `/`, return an error.
+///   3. If the path contains `{placeholder}`s, expand them (see
+///      [`pass::expand_template()`]) before applying the rules below.
+///   4. If the path ends with `/`, return a [`PassPath`] that uses this value
+///      as the directory under the password store root where tokens are
+///      stored.
+///   5. If the path does not end with `/`, return a [`PassPath`] that uses
+///      this exact value as the storage path for the token.
 ///
-///   1. If args is empty, return a [`PassPath`] derived from `registry`.
-///   2. If args contains more than 1 entry, return an error.
-///   3. If args contains exactly one entry, and starts with `/`, return an
-///      error.
-///   4. If args contains exactly one entry, and ends with `/`, return a
-///      [`PassPath`] that uses this value as the directory under the password
-///      store root where tokens are stored.
-///   5. If args contains exactly one entry, and does not end with `/`, return a
-///      [`PassPath`] that uses this exact value as the storage path for the
-///      token.
+/// If `operation` is provided, the returned [`PassPath`] addresses the token
+/// specific to that operation - the exact path configured for the operation if
+/// any, otherwise for exact paths the operation name is appended as a suffix
+/// (`<path>.<operation>`) unless the path is a template using `{operation}`.
+/// The `crate_name` is only used to expand templates.
 fn path_from_args(
-    args: &[&str],
+    args: &ProviderArgs<'_>,
     registry: &RegistryInfo<'_>,
+    operation: Option<&'static str>,
+    crate_name: Option<&str>,
 ) -> Result<PassPath, cargo_credential::Error> {
+    let placeholders = pass::Placeholders {
+        name: registry.name,
+        index_url: registry.index_url,
+        operation,
+        crate_name,
+    };
+
+    if let Some((_, path)) = args
+        .operation_paths
+        .iter()
+        .find(|(op, _)| Some(*op) == operation)
+    {
+        let path = match pass::is_template(path) {
+            true => pass::expand_template(path, &placeholders)?,
+            false => path.to_string(),
+        };
+        return Ok(PassPath::new(PathBuf::from(path))?);
+    }
+
     let mut p = PassPathBuilder::default();
     if let Some(name) = registry.name {
         p = p.with_name(name);
     }
+    if let Some(op) = operation {
+        p = p.for_operation(op);
+    }
 
-    // Accept exactly 0 or 1 arguments.
-    let path = match args {
-        [] => return Ok(p.build(registry.index_url)),
-        [path] => path,
-        [_, ..] => {
-            return Err(cargo_credential::Error::Other(
-                "too many arguments specified in cargo credential provider config".into(),
-            ))
-        }
+    let Some(path) = args.path else {
+        return Ok(p.build(registry.index_url)?);
     };
 
     // Disallow absolute paths, as they're always rooted under the password
@@ -121,14 +550,67 @@ fn path_from_args(
         ));
     }
 
+    // A template that uses the operation addresses per-operation tokens
+    // itself.
+    let (path, op_suffix) = match pass::is_template(path) {
+        true => (
+            Cow::Owned(pass::expand_template(path, &placeholders)?),
+            !path.contains("{operation}"),
+        ),
+        false => (Cow::Borrowed(path), true),
+    };
+
     // A path that ends with a `/` is specifying a directory tokens are stored
     // in.
     if path.ends_with('/') {
-        return Ok(p.under_dir(Path::new(path)).build(registry.index_url));
+        return Ok(p
+            .under_dir(Path::new(path.as_ref()))
+            .build(registry.index_url)?);
     }
 
     // Otherwise this path specifies the exact token file path to use.
-    Ok(PassPath::new(PathBuf::from(path.to_string())))
+    match operation {
+        Some(op) if op_suffix => Ok(PassPath::new(PathBuf::from(format!("{path}.{op}")))?),
+        _ => Ok(PassPath::new(PathBuf::from(path.into_owned()))?),
+    }
+}
+
+/// Return the path a token derived from `args` for `registry` was stored at
+/// by earlier releases, if it differs from [`path_from_args()`].
+///
+/// Earlier releases derived the name of an unnamed registry by replacing all
+/// non-alphanumeric characters in the index URL with `_`, mapping distinct URLs
+/// to the same token - tokens stored at these paths are still read (and removed
+/// on logout), but new tokens are always stored at the current path.
+fn legacy_path_from_args(
+    args: &ProviderArgs<'_>,
+    registry: &RegistryInfo<'_>,
+    operation: Option<&'static str>,
+) -> Result<Option<PassPath>, cargo_credential::Error> {
+    // Only names derived for unnamed registries have changed.
+    if registry.name.is_some()
+        || args
+            .operation_paths
+            .iter()
+            .any(|(op, _)| Some(*op) == operation)
+    {
+        return Ok(None);
+    }
+
+    let mut p = PassPathBuilder::default().with_legacy_name();
+    if let Some(op) = operation {
+        p = p.for_operation(op);
+    }
+
+    match args.path {
+        None => {}
+        Some(path) if path.ends_with('/') && !path.starts_with('/') && !pass::is_template(path) => {
+            p = p.under_dir(Path::new(path));
+        }
+        Some(_) => return Ok(None),
+    }
+
+    Ok(Some(p.build(registry.index_url)?))
 }
 
 pub fn main() {
@@ -137,6 +619,15 @@ pub fn main() {
 
     match args_str.as_slice() {
         [_, "--cargo-plugin"] => cargo_credential::main(Dispatch {}),
+        [_, cmd, rest @ ..] => match cli::Command::parse(cmd) {
+            Some(cmd) => {
+                if let Err(e) = cmd.run(rest) {
+                    eprintln!("error: {e}");
+                    std::process::exit(1);
+                }
+            }
+            None => print_help(),
+        },
         _ => print_help(),
     }
 }
@@ -156,10 +647,28 @@ fn print_help() {
     eprintln!();
     eprintln!("\thttps://doc.rust-lang.org/cargo/reference/registry-authentication.html");
     eprintln!();
+    eprintln!("Tokens can also be managed directly:");
+    eprintln!();
+    eprintln!("\tcargo-credential-pass list [<dir>/] [args...]");
+    eprintln!("\tcargo-credential-pass show-path <registry-or-url> [args...]");
+    eprintln!("\tcargo-credential-pass status <registry-or-url> [args...]");
+    eprintln!("\tcargo-credential-pass login <registry-or-url> [args...]");
+    eprintln!("\tcargo-credential-pass logout <registry-or-url> [args...]");
+    eprintln!("\tcargo-credential-pass import [--rewrite] [args...]");
+    eprintln!("\tcargo-credential-pass doctor [--registry=<registry-or-url>] [args...]");
+    eprintln!();
+    eprintln!(
+        "Where [args...] are the same arguments as configured for the \
+        credential provider in cargo, and login reads the token from stdin \
+        if it is not a terminal."
+    );
+    eprintln!();
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
     const REG: RegistryInfo = RegistryInfo {
@@ -168,28 +677,308 @@ mod tests {
         headers: vec![],
     };
 
+    fn path_from_args(args: &[&str], registry: &RegistryInfo<'_>) -> PassPath {
+        let args = ProviderArgs::parse(args).expect("valid args");
+        super::path_from_args(&args, registry, None, None).expect("valid path")
+    }
+
     #[test]
     fn test_path_from_no_custom_path() {
-        let got = path_from_args(&[], &REG).expect("valid path").to_string();
+        let got = path_from_args(&[], &REG).to_string();
 
         assert_eq!(got, "cargo-registry/bananas.token");
     }
 
     #[test]
     fn test_path_from_with_custom_path() {
-        let got = path_from_args(&["tokens/go/here"], &REG)
-            .expect("valid path")
-            .to_string();
+        let got = path_from_args(&["tokens/go/here"], &REG).to_string();
 
         assert_eq!(got, "tokens/go/here");
     }
 
     #[test]
     fn test_path_from_with_custom_dir() {
-        let got = path_from_args(&["tokens/go/here/"], &REG)
-            .expect("valid path")
-            .to_string();
+        let got = path_from_args(&["tokens/go/here/"], &REG).to_string();
 
         assert_eq!(got, "tokens/go/here/bananas.token");
     }
+
+    #[test]
+    fn test_path_from_with_operation() {
+        let path = |args: &[&str]| {
+            let args = ProviderArgs::parse(args).expect("valid args");
+            super::path_from_args(&args, &REG, Some("publish"), None)
+                .expect("valid path")
+                .to_string()
+        };
+
+        assert_eq!(path(&[]), "cargo-registry/bananas.publish.token");
+        assert_eq!(
+            path(&["tokens/go/here/"]),
+            "tokens/go/here/bananas.publish.token"
+        );
+        assert_eq!(path(&["tokens/go/here"]), "tokens/go/here.publish");
+    }
+
+    #[test]
+    fn test_path_from_with_configured_operation() {
+        let operations = [("publish", "work/publish".to_string())];
+        let args = ProviderArgs {
+            path: Some("tokens/go/here"),
+            operation_paths: &operations,
+            ..Default::default()
+        };
+
+        let got = super::path_from_args(&args, &REG, Some("publish"), None).expect("valid path");
+        assert_eq!(got.to_string(), "work/publish");
+
+        let got = super::path_from_args(&args, &REG, Some("yank"), None).expect("valid path");
+        assert_eq!(got.to_string(), "tokens/go/here.yank");
+    }
+
+    #[test]
+    fn test_legacy_path_from_args() {
+        let unnamed = RegistryInfo {
+            index_url: "sparse+https://itsallbroken.com/index/",
+            name: None,
+            headers: vec![],
+        };
+        let legacy = |args: &[&str], registry: &RegistryInfo<'_>, operation| {
+            let args = ProviderArgs::parse(args).expect("valid args");
+            super::legacy_path_from_args(&args, registry, operation)
+                .expect("valid path")
+                .map(|v| v.to_string())
+        };
+
+        assert_eq!(
+            legacy(&[], &unnamed, None).as_deref(),
+            Some("cargo-registry/https___itsallbroken_com_index_.token")
+        );
+        assert_eq!(
+            legacy(&["tokens/"], &unnamed, Some("publish")).as_deref(),
+            Some("tokens/https___itsallbroken_com_index_.publish.token")
+        );
+
+        // Named registries, exact paths and templates have no legacy path.
+        assert_eq!(legacy(&[], &REG, None), None);
+        assert_eq!(legacy(&["tokens/go/here"], &unnamed, None), None);
+        assert_eq!(legacy(&["tokens/{host}/"], &unnamed, None), None);
+    }
+
+    #[test]
+    fn test_candidate_paths() {
+        let registry = RegistryInfo {
+            index_url: "sparse+https://itsallbroken.com/index/",
+            name: Some("bananas"),
+            headers: vec![],
+        };
+        let candidates = |args: &[&str], registry: &RegistryInfo<'_>, operation| {
+            let args = ProviderArgs::parse(args).expect("valid args");
+            super::candidate_paths(&args, registry, operation, None)
+                .expect("valid paths")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            candidates(&[], &registry, None),
+            [
+                "cargo-registry/bananas.token",
+                "cargo-registry/itsallbroken.com/689322e6387231a7.token",
+                "cargo-registry/https___itsallbroken_com_index_.token",
+            ]
+        );
+        assert_eq!(
+            candidates(&["tokens/"], &registry, Some("publish")),
+            [
+                "tokens/bananas.publish.token",
+                "tokens/itsallbroken.com/689322e6387231a7.publish.token",
+                "tokens/https___itsallbroken_com_index_.publish.token",
+            ]
+        );
+
+        // An exact path is the only candidate.
+        assert_eq!(
+            candidates(&["tokens/go/here"], &registry, None),
+            ["tokens/go/here"]
+        );
+
+        // A registry named on the command line has no URL candidates.
+        let named = RegistryInfo {
+            index_url: "",
+            name: Some("bananas"),
+            headers: vec![],
+        };
+        assert_eq!(
+            candidates(&[], &named, None),
+            ["cargo-registry/bananas.token"]
+        );
+    }
+
+    #[test]
+    fn test_path_from_with_template() {
+        let path = |args: &[&str], operation, crate_name| {
+            let args = ProviderArgs::parse(args).expect("valid args");
+            super::path_from_args(&args, &REG, operation, crate_name).map(|v| v.to_string())
+        };
+
+        assert_eq!(
+            path(&["cargo/{host}/{name}.token"], None, None).unwrap(),
+            "cargo/itsallbroken.com/bananas.token"
+        );
+        assert_eq!(
+            path(&["tokens/{registry}/{operation}"], None, None).unwrap(),
+            "tokens/bananas/default"
+        );
+        assert_eq!(
+            path(&["tokens/{registry}/{operation}"], Some("publish"), None).unwrap(),
+            "tokens/bananas/publish"
+        );
+        assert_eq!(
+            path(&["tokens/{crate}.token"], Some("publish"), Some("platanos")).unwrap(),
+            "tokens/platanos.token.publish"
+        );
+
+        // A template ending in a `/` is a directory.
+        assert_eq!(
+            path(&["cargo/{host}/"], Some("yank"), None).unwrap(),
+            "cargo/itsallbroken.com/bananas.yank.token"
+        );
+
+        assert_eq!(
+            path(&["tokens/{name}/../../{name}"], None, None)
+                .unwrap_err()
+                .to_string(),
+            "invalid token path template 'tokens/{name}/../../{name}': \
+            expands to 'tokens/bananas/../../bananas' containing '..'"
+        );
+    }
+
+    #[test]
+    fn test_path_from_with_template_operation() {
+        let operations = [("publish", "release/{crate}.token".to_string())];
+        let args = ProviderArgs {
+            operation_paths: &operations,
+            ..Default::default()
+        };
+
+        let got = super::path_from_args(&args, &REG, Some("publish"), Some("platanos"))
+            .expect("valid path");
+        assert_eq!(got.to_string(), "release/platanos.token");
+    }
+
+    #[test]
+    fn test_is_operation_templated() {
+        let templated = |path: Option<&str>, operations: &[(&'static str, String)]| {
+            is_operation_templated(&ProviderArgs {
+                path,
+                operation_paths: operations,
+                ..Default::default()
+            })
+        };
+
+        assert!(!templated(None, &[]));
+        assert!(!templated(Some("tokens/{host}/{name}"), &[]));
+        assert!(templated(Some("tokens/{crate}"), &[]));
+        assert!(templated(Some("tokens/{name}/{operation}"), &[]));
+        assert!(templated(
+            Some("tokens/go/here"),
+            &[("publish", "release/{crate}.token".to_string())]
+        ));
+    }
+
+    const NOW: i64 = 1733043600;
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn cache_control(entry: &str) -> Result<CacheControl, cargo_credential::Error> {
+        cache_control_with(entry, CachePolicy::Expires)
+    }
+
+    fn cache_control_with(
+        entry: &str,
+        policy: CachePolicy,
+    ) -> Result<CacheControl, cargo_credential::Error> {
+        super::cache_control(
+            &path_from_args(&[], &REG),
+            &Entry::parse(entry),
+            policy,
+            DAY,
+            OffsetDateTime::from_unix_timestamp(NOW).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_cache_control_no_expiry() {
+        let got = cache_control("bananas\n").expect("valid entry");
+        assert_eq!(got, CacheControl::Session);
+    }
+
+    #[test]
+    fn test_cache_control_expires() {
+        for expires in [NOW + 60, NOW + 2 * DAY.as_secs() as i64] {
+            let got = cache_control(&format!("bananas\nexpires: {expires}\n")).expect("valid");
+            assert_eq!(
+                got,
+                CacheControl::Expires {
+                    expiration: OffsetDateTime::from_unix_timestamp(expires).unwrap()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_control_policy() {
+        let expires = format!("bananas\nexpires: {}\n", NOW + 60);
+
+        for (policy, want) in [
+            (CachePolicy::Session, CacheControl::Session),
+            (CachePolicy::Never, CacheControl::Never),
+        ] {
+            assert_eq!(cache_control_with("bananas\n", policy).unwrap(), want);
+            assert_eq!(cache_control_with(&expires, policy).unwrap(), want);
+        }
+
+        // Expired tokens are never returned.
+        cache_control_with(
+            "bananas\nexpires: 2024-12-01T09:00:00Z\n",
+            CachePolicy::Never,
+        )
+        .expect_err("expired");
+    }
+
+    #[test]
+    fn test_cache_control_expired() {
+        let got = cache_control("bananas\nexpires: 2024-12-01T09:00:00Z\n").expect_err("expired");
+        assert_eq!(
+            got.to_string(),
+            "token in cargo-registry/bananas.token expired at 2024-12-01T09:00:00Z, \
+            use 'cargo login' to store a new token"
+        );
+    }
+
+    proptest! {
+        /// An exact path argument addresses the token at that path (suffixed
+        /// by any operation), and a directory argument addresses the named
+        /// registry's token within it.
+        #[test]
+        fn prop_path_from_args_layout(
+            path in "[a-z]{1,8}(/[a-z]{1,8}){0,2}",
+            op in proptest::option::of(proptest::sample::select(OPERATIONS.to_vec())),
+        ) {
+            let get = |arg: &str| {
+                let args = ProviderArgs::parse(&[arg]).expect("valid args");
+                super::path_from_args(&args, &REG, op, None)
+                    .expect("valid path")
+                    .to_string()
+            };
+
+            let (exact, token) = match op {
+                Some(op) => (format!("{path}.{op}"), format!("bananas.{op}.token")),
+                None => (path.clone(), "bananas.token".to_string()),
+            };
+            prop_assert_eq!(get(&path), exact);
+            prop_assert_eq!(get(&format!("{path}/")), format!("{path}/{token}"));
+        }
+    }
 }
@@ -0,0 +1,391 @@
+//! Asymmetric token support ([RFC 3231]).
+//!
+//! Rather than storing a registry token, the password store holds a [PASERK]
+//! encoded PASETO v3 secret key, which is used to mint a signed [PASETO] token
+//! for each request - the same token format as cargo's built-in `cargo:paseto`
+//! provider.
+//!
+//! [RFC 3231]: https://rust-lang.github.io/rfcs/3231-cargo-asymmetric-tokens.html
+//! [PASETO]: https://github.com/paseto-standard/paseto-spec/blob/master/docs/01-Protocol-Versions/Version3.md
+//! [PASERK]: https://github.com/paseto-standard/paserk
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::time::Duration;
+
+use cargo_credential::{CacheControl, Operation, Secret};
+use p384::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use rand_core::OsRng;
+use sha2::{Digest, Sha384};
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// The PASERK prefix of a v3 secret key.
+const SECRET_PREFIX: &str = "k3.secret.";
+
+/// The PASERK prefix of a v3 public key.
+const PUBLIC_PREFIX: &str = "k3.public.";
+
+/// The PASERK prefix of a v3 public key ID.
+const PID_PREFIX: &str = "k3.pid.";
+
+/// The PASETO header of a v3 public (signed) token.
+const TOKEN_HEADER: &str = "v3.public.";
+
+/// How long cargo may reuse a signed token for read operations - registries
+/// reject tokens with a stale `iat` issue time.
+const READ_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Failures minting asymmetric tokens.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    /// The stored key is not a valid PASERK v3 secret key.
+    #[error("invalid PASERK secret key (expected k3.secret.<key>)")]
+    InvalidKey,
+
+    /// The token timestamp could not be formatted.
+    #[error("failed to format token timestamp: {0}")]
+    Timestamp(#[from] time::error::Format),
+}
+
+/// Returns true if `token` is a PASERK v3 secret key, rather than a registry
+/// token.
+pub(crate) fn is_secret_key(token: &str) -> bool {
+    token.starts_with(SECRET_PREFIX)
+}
+
+/// A PASETO v3 (NIST P-384) secret key.
+pub(crate) struct SecretKey(p384::SecretKey);
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SecretKey").field(&"REDACTED").finish()
+    }
+}
+
+impl SecretKey {
+    /// Generate a new random secret key.
+    pub(crate) fn generate() -> Self {
+        Self(p384::SecretKey::random(&mut OsRng))
+    }
+
+    /// Decode a PASERK `k3.secret` encoded key.
+    pub(crate) fn from_paserk(v: &str) -> Result<Self, Error> {
+        let bytes = v
+            .strip_prefix(SECRET_PREFIX)
+            .and_then(|v| URL_SAFE_NO_PAD.decode(v).ok())
+            .filter(|v| v.len() == 48)
+            .ok_or(Error::InvalidKey)?;
+
+        p384::SecretKey::from_slice(&bytes)
+            .map(Self)
+            .map_err(|_| Error::InvalidKey)
+    }
+
+    /// Encode this key as a PASERK `k3.secret` string.
+    pub(crate) fn to_paserk(&self) -> Secret<String> {
+        Secret::from(format!(
+            "{SECRET_PREFIX}{}",
+            URL_SAFE_NO_PAD.encode(self.0.to_bytes())
+        ))
+    }
+
+    /// Encode the public key for this secret key as a PASERK `k3.public`
+    /// string, suitable for registering with a registry.
+    pub(crate) fn public_paserk(&self) -> String {
+        format!(
+            "{PUBLIC_PREFIX}{}",
+            URL_SAFE_NO_PAD.encode(self.public_key_bytes())
+        )
+    }
+
+    /// Return the PASERK `k3.pid` ID of the public key.
+    fn public_key_id(&self) -> String {
+        let hash = Sha384::new()
+            .chain_update(PID_PREFIX)
+            .chain_update(self.public_paserk())
+            .finalize();
+
+        // The ID is the hash truncated to 264 bits.
+        format!("{PID_PREFIX}{}", URL_SAFE_NO_PAD.encode(&hash[..33]))
+    }
+
+    /// The compressed SEC1 encoding of the public key.
+    fn public_key_bytes(&self) -> Vec<u8> {
+        self.0
+            .public_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Sign `message` and `footer`, returning a `v3.public` PASETO token.
+    fn sign(&self, message: &[u8], footer: &[u8]) -> String {
+        let pk = self.public_key_bytes();
+        let m2 = pae(&[&pk, TOKEN_HEADER.as_bytes(), message, footer, &[]]);
+
+        let sig: Signature = SigningKey::from(&self.0).sign(&m2);
+
+        let mut body = message.to_vec();
+        body.extend_from_slice(&sig.to_bytes());
+
+        let mut token = format!("{TOKEN_HEADER}{}", URL_SAFE_NO_PAD.encode(body));
+        if !footer.is_empty() {
+            token.push('.');
+            token.push_str(&URL_SAFE_NO_PAD.encode(footer));
+        }
+
+        token
+    }
+}
+
+/// Mint a signed token authorising `op` against the registry at `index_url`,
+/// issued at `now`.
+///
+/// The token claims match those of cargo's `cargo:paseto` provider - the
+/// optional key `subject`, the operation being performed and the crate name,
+/// version and checksum it applies to. Challenges are never included, as cargo
+/// does not provide one to credential providers.
+pub(crate) fn token(
+    key: &SecretKey,
+    subject: Option<&str>,
+    op: &Operation<'_>,
+    index_url: &str,
+    now: OffsetDateTime,
+) -> Result<Secret<String>, Error> {
+    let (mutation, name, vers, cksum) = match *op {
+        Operation::Publish { name, vers, cksum } => {
+            (Some("publish"), Some(name), Some(vers), Some(cksum))
+        }
+        Operation::Yank { name, vers } => (Some("yank"), Some(name), Some(vers), None),
+        Operation::Unyank { name, vers } => (Some("unyank"), Some(name), Some(vers), None),
+        Operation::Owners { name } => (Some("owners"), Some(name), None, None),
+        _ => (None, None, None, None),
+    };
+
+    let mut message = serde_json::Map::new();
+    message.insert("iat".into(), now.format(&Rfc3339)?.into());
+    for (k, v) in [
+        ("sub", subject),
+        ("mutation", mutation),
+        ("name", name),
+        ("vers", vers),
+        ("cksum", cksum),
+    ] {
+        if let Some(v) = v {
+            message.insert(k.into(), v.into());
+        }
+    }
+
+    let footer = serde_json::json!({
+        "url": index_url,
+        "kip": key.public_key_id(),
+    });
+
+    Ok(Secret::from(key.sign(
+        serde_json::Value::Object(message).to_string().as_bytes(),
+        footer.to_string().as_bytes(),
+    )))
+}
+
+/// Return the [`CacheControl`] of a token minted for `op` at `now`, from the
+/// `cache` control of the stored key.
+///
+/// Only tokens for read operations are reused, and for no longer than
+/// [`READ_TOKEN_LIFETIME`].
+pub(crate) fn cache_control(
+    op: &Operation<'_>,
+    cache: CacheControl,
+    now: OffsetDateTime,
+) -> CacheControl {
+    let expiration = now + READ_TOKEN_LIFETIME;
+
+    match (op, cache) {
+        (Operation::Read, CacheControl::Expires { expiration: v }) => CacheControl::Expires {
+            expiration: v.min(expiration),
+        },
+        (Operation::Read, CacheControl::Session) => CacheControl::Expires { expiration },
+        _ => CacheControl::Never,
+    }
+}
+
+/// Pre-Authentication Encoding of `pieces`, as defined by the PASETO spec.
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    fn le64(n: usize) -> [u8; 8] {
+        ((n as u64) & (u64::MAX >> 1)).to_le_bytes()
+    }
+
+    let mut out = le64(pieces.len()).to_vec();
+    for p in pieces {
+        out.extend_from_slice(&le64(p.len()));
+        out.extend_from_slice(p);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use p384::ecdsa::{signature::Verifier, VerifyingKey};
+
+    use super::*;
+
+    #[test]
+    fn test_pae() {
+        assert_eq!(pae(&[]), b"\x00\x00\x00\x00\x00\x00\x00\x00");
+        assert_eq!(
+            pae(&[b""]),
+            b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00"
+        );
+        assert_eq!(
+            pae(&[b"test"]),
+            b"\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00test"
+        );
+    }
+
+    #[test]
+    fn test_paserk_round_trip() {
+        let key = SecretKey::generate();
+        let encoded = key.to_paserk().expose();
+
+        assert!(is_secret_key(&encoded));
+
+        let decoded = SecretKey::from_paserk(&encoded).expect("valid key");
+        assert_eq!(decoded.public_paserk(), key.public_paserk());
+        assert!(key.public_paserk().starts_with("k3.public."));
+    }
+
+    #[test]
+    fn test_invalid_paserk() {
+        for v in [
+            "bananas",
+            "k3.secret.",
+            "k3.secret.!!!",
+            "k3.secret.AAAA",
+            "k4.secret.AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+        ] {
+            assert!(
+                matches!(SecretKey::from_paserk(v), Err(Error::InvalidKey)),
+                "{v} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_public_key_id() {
+        let key = SecretKey::generate();
+        let id = key.public_key_id();
+
+        let encoded = id.strip_prefix("k3.pid.").expect("pid prefix");
+        assert_eq!(URL_SAFE_NO_PAD.decode(encoded).unwrap().len(), 33);
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let now = OffsetDateTime::from_unix_timestamp(1733043600).unwrap();
+        let expires = |secs| CacheControl::Expires {
+            expiration: now + Duration::from_secs(secs),
+        };
+
+        // Read tokens are reused briefly, or until the key expires.
+        assert_eq!(
+            cache_control(&Operation::Read, CacheControl::Session, now),
+            expires(60)
+        );
+        assert_eq!(
+            cache_control(&Operation::Read, expires(3600), now),
+            expires(60)
+        );
+        assert_eq!(
+            cache_control(&Operation::Read, expires(30), now),
+            expires(30)
+        );
+        assert_eq!(
+            cache_control(&Operation::Read, CacheControl::Never, now),
+            CacheControl::Never
+        );
+
+        // Tokens for mutations are never reused.
+        let op = Operation::Yank {
+            name: "bananas",
+            vers: "1.0.0",
+        };
+        assert_eq!(
+            cache_control(&op, CacheControl::Session, now),
+            CacheControl::Never
+        );
+    }
+
+    #[test]
+    fn test_token() {
+        let key = SecretKey::generate();
+        let now = OffsetDateTime::from_unix_timestamp(1733043600).unwrap();
+        let op = Operation::Publish {
+            name: "bananas",
+            vers: "1.0.0",
+            cksum: "42",
+        };
+
+        let token = token(&key, Some("platanos"), &op, "https://itsallbroken.com", now)
+            .expect("valid token")
+            .expose();
+
+        let (body, footer) = token
+            .strip_prefix("v3.public.")
+            .expect("token header")
+            .split_once('.')
+            .expect("has footer");
+
+        let footer = URL_SAFE_NO_PAD.decode(footer).unwrap();
+        assert_eq!(
+            String::from_utf8(footer.clone()).unwrap(),
+            format!(
+                r#"{{"kip":"{}","url":"https://itsallbroken.com"}}"#,
+                key.public_key_id()
+            )
+        );
+
+        let body = URL_SAFE_NO_PAD.decode(body).unwrap();
+        let (message, sig) = body.split_at(body.len() - 96);
+        assert_eq!(
+            std::str::from_utf8(message).unwrap(),
+            r#"{"cksum":"42","iat":"2024-12-01T09:00:00Z","mutation":"publish","name":"bananas","sub":"platanos","vers":"1.0.0"}"#
+        );
+
+        // Verify the signature.
+        let pk = key.public_key_bytes();
+        let m2 = pae(&[&pk, b"v3.public.", message, &footer, b""]);
+        let sig = Signature::from_slice(sig).unwrap();
+        VerifyingKey::from(key.0.public_key())
+            .verify(&m2, &sig)
+            .expect("valid signature");
+    }
+
+    #[test]
+    fn test_token_read() {
+        let key = SecretKey::generate();
+        let now = OffsetDateTime::from_unix_timestamp(1733043600).unwrap();
+
+        let token = token(
+            &key,
+            None,
+            &Operation::Read,
+            "https://itsallbroken.com",
+            now,
+        )
+        .expect("valid");
+
+        let body = token
+            .expose()
+            .strip_prefix("v3.public.")
+            .and_then(|v| v.split_once('.'))
+            .map(|(body, _)| URL_SAFE_NO_PAD.decode(body).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&body[..body.len() - 96]).unwrap(),
+            r#"{"iat":"2024-12-01T09:00:00Z"}"#
+        );
+    }
+}
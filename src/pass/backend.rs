@@ -0,0 +1,437 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use super::PassPath;
+
+/// The env var used to select a [`Backend`] when not specified in the provider
+/// arguments.
+pub(crate) const BACKEND_ENV: &str = "CARGO_CREDENTIAL_PASS_BACKEND";
+
+/// A password manager CLI that stores secrets in a `pass`-like store.
+///
+/// Implementations describe how to drive their CLI - the [`PassKeychain`]
+/// executes the commands and handles the results.
+///
+/// [`PassKeychain`]: super::PassKeychain
+pub(crate) trait Backend: Debug {
+    /// Construct a command that inserts or overwrites the entry at `path`,
+    /// reading the (potentially multi-line) entry from stdin until EOF.
+    fn upsert(&self, path: &PassPath) -> Command;
+
+    /// Construct a command that opens the user's `$EDITOR` to edit the entry
+    /// at `path`.
+    fn edit(&self, path: &PassPath) -> Command;
+
+    /// Construct a command that prints the full entry at `path` to stdout,
+    /// including any metadata lines.
+    fn read(&self, path: &PassPath) -> Command;
+
+    /// Construct a command that deletes the entry at `path`.
+    fn delete(&self, path: &PassPath) -> Command;
+
+    /// Construct a command that prints the version of the backend CLI.
+    fn version(&self) -> Command;
+
+    /// Return the root directory of the store.
+    fn store_dir(&self) -> PathBuf;
+
+    /// Return the env var the backend CLI reads the store root directory
+    /// from.
+    fn store_dir_env(&self) -> &'static str;
+
+    /// Return the name of the file listing the encryption recipients of the
+    /// directory that contains it (and any sub-directories).
+    fn recipients_file(&self) -> &'static str;
+
+    /// Return the file extension of encrypted entries in the store, including
+    /// the leading `.`.
+    fn extension(&self) -> &'static str;
+
+    /// Return the file that stores the entry at `path` in the store rooted at
+    /// `root`.
+    fn entry_file(&self, root: &Path, path: &PassPath) -> PathBuf {
+        with_extension(root.join(path.as_ref()), self.extension())
+    }
+
+    /// Return the GPG encrypted file for `path` in the store rooted at `root`
+    /// if it can be decrypted directly with `gpg`, without invoking the
+    /// backend CLI.
+    fn gpg_file(&self, _root: &Path, _path: &PassPath) -> Option<PathBuf> {
+        None
+    }
+
+    /// Returns true if every entry is stored at [`Backend::entry_file()`] in
+    /// the store root, so an entry without a file there does not exist.
+    fn fixed_layout(&self) -> bool {
+        true
+    }
+}
+
+/// The set of supported [`Backend`] implementations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum BackendKind {
+    /// The standard unix password manager.
+    #[default]
+    Pass,
+    /// <https://www.gopass.pw/>
+    Gopass,
+    /// <https://github.com/FiloSottile/passage>
+    Passage,
+}
+
+impl BackendKind {
+    /// Read the [`BackendKind`] from the [`BACKEND_ENV`] env var, if set.
+    pub(crate) fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var(BACKEND_ENV) {
+            Ok(v) => v.parse().map(Some),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(_)) => {
+                Err(format!("invalid (non-utf8) value for {BACKEND_ENV}"))
+            }
+        }
+    }
+
+    /// Construct the [`Backend`] implementation for this kind.
+    pub(crate) fn into_backend(self) -> Box<dyn Backend> {
+        match self {
+            Self::Pass => Box::new(Pass),
+            Self::Gopass => Box::new(Gopass),
+            Self::Passage => Box::new(Passage),
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pass" => Ok(Self::Pass),
+            "gopass" => Ok(Self::Gopass),
+            "passage" => Ok(Self::Passage),
+            v => Err(format!(
+                "unknown backend '{v}' (expected one of: pass, gopass, passage)"
+            )),
+        }
+    }
+}
+
+/// [`pass`] - the standard unix password manager.
+///
+/// [`pass`]: https://www.passwordstore.org/
+#[derive(Debug, Default)]
+pub(crate) struct Pass;
+
+impl Backend for Pass {
+    fn upsert(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("pass");
+        c.arg("insert").arg("--multiline").arg("--force").arg(path);
+        c
+    }
+
+    fn edit(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("pass");
+        c.arg("edit").arg(path);
+        c
+    }
+
+    fn read(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("pass");
+        c.arg("show").arg(path);
+        c
+    }
+
+    fn delete(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("pass");
+        c.arg("rm").arg("--force").arg(path);
+        c
+    }
+
+    fn version(&self) -> Command {
+        let mut c = Command::new("pass");
+        c.arg("version");
+        c
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        std::env::var_os("PASSWORD_STORE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home_dir().join(".password-store"))
+    }
+
+    fn store_dir_env(&self) -> &'static str {
+        "PASSWORD_STORE_DIR"
+    }
+
+    fn extension(&self) -> &'static str {
+        ".gpg"
+    }
+
+    fn recipients_file(&self) -> &'static str {
+        ".gpg-id"
+    }
+
+    fn gpg_file(&self, root: &Path, path: &PassPath) -> Option<PathBuf> {
+        Some(self.entry_file(root, path))
+    }
+}
+
+/// [`gopass`] - a `pass` compatible password manager.
+///
+/// Only entries in the root store are checked for existence - entries in
+/// mounted sub-stores are still readable, but a failure to read them is never
+/// reported as "not found".
+///
+/// [`gopass`]: https://www.gopass.pw/
+#[derive(Debug, Default)]
+pub(crate) struct Gopass;
+
+impl Backend for Gopass {
+    fn upsert(&self, path: &PassPath) -> Command {
+        // gopass reads the whole of stdin as the entry when it is not a
+        // terminal.
+        let mut c = Command::new("gopass");
+        c.arg("insert").arg("-f").arg(path);
+        c
+    }
+
+    fn edit(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("gopass");
+        c.arg("edit").arg(path);
+        c
+    }
+
+    fn read(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("gopass");
+        c.arg("show").arg("--noparsing").arg(path);
+        c
+    }
+
+    fn delete(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("gopass");
+        c.arg("rm").arg("-f").arg(path);
+        c
+    }
+
+    fn version(&self) -> Command {
+        let mut c = Command::new("gopass");
+        c.arg("version");
+        c
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        std::env::var_os("PASSWORD_STORE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                std::env::var_os("XDG_DATA_HOME")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| home_dir().join(".local/share"))
+                    .join("gopass/stores/root")
+            })
+    }
+
+    fn store_dir_env(&self) -> &'static str {
+        "PASSWORD_STORE_DIR"
+    }
+
+    fn extension(&self) -> &'static str {
+        ".gpg"
+    }
+
+    fn recipients_file(&self) -> &'static str {
+        ".gpg-id"
+    }
+
+    fn fixed_layout(&self) -> bool {
+        // Entries may be in a mounted sub-store.
+        false
+    }
+}
+
+/// [`passage`] - a fork of `pass` that uses [`age`] for encryption.
+///
+/// [`passage`]: https://github.com/FiloSottile/passage
+/// [`age`]: https://age-encryption.org/
+#[derive(Debug, Default)]
+pub(crate) struct Passage;
+
+impl Backend for Passage {
+    fn upsert(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("passage");
+        c.arg("insert").arg("--multiline").arg("--force").arg(path);
+        c
+    }
+
+    fn edit(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("passage");
+        c.arg("edit").arg(path);
+        c
+    }
+
+    fn read(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("passage");
+        c.arg("show").arg(path);
+        c
+    }
+
+    fn delete(&self, path: &PassPath) -> Command {
+        let mut c = Command::new("passage");
+        c.arg("rm").arg("--force").arg(path);
+        c
+    }
+
+    fn version(&self) -> Command {
+        let mut c = Command::new("passage");
+        c.arg("version");
+        c
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        std::env::var_os("PASSAGE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home_dir().join(".passage/store"))
+    }
+
+    fn store_dir_env(&self) -> &'static str {
+        "PASSAGE_DIR"
+    }
+
+    fn extension(&self) -> &'static str {
+        ".age"
+    }
+
+    fn recipients_file(&self) -> &'static str {
+        ".age-recipients"
+    }
+}
+
+/// Return the user's home directory, or an empty path if it cannot be
+/// resolved.
+fn home_dir() -> PathBuf {
+    std::env::home_dir().unwrap_or_default()
+}
+
+/// Append `ext` to the filename of `path`.
+///
+/// Unlike [`PathBuf::set_extension()`] this never replaces an existing
+/// extension, as token names commonly contain a `.` already.
+fn with_extension(mut path: PathBuf, ext: &str) -> PathBuf {
+    path.as_mut_os_string().push(ext);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(c: &Command) -> Vec<String> {
+        std::iter::once(c.get_program())
+            .chain(c.get_args())
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_backend_kind_from_str() {
+        assert_eq!("pass".parse(), Ok(BackendKind::Pass));
+        assert_eq!("gopass".parse(), Ok(BackendKind::Gopass));
+        assert_eq!("passage".parse(), Ok(BackendKind::Passage));
+        assert_eq!(
+            "bananas".parse::<BackendKind>(),
+            Err("unknown backend 'bananas' (expected one of: pass, gopass, passage)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pass_commands() {
+        let p = PassPath::new(PathBuf::from("cargo-registry/bananas.token")).unwrap();
+
+        assert_eq!(
+            args(&Pass.upsert(&p)),
+            [
+                "pass",
+                "insert",
+                "--multiline",
+                "--force",
+                "cargo-registry/bananas.token"
+            ]
+        );
+        assert_eq!(
+            args(&Pass.edit(&p)),
+            ["pass", "edit", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Pass.read(&p)),
+            ["pass", "show", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Pass.delete(&p)),
+            ["pass", "rm", "--force", "cargo-registry/bananas.token"]
+        );
+    }
+
+    #[test]
+    fn test_gopass_commands() {
+        let p = PassPath::new(PathBuf::from("cargo-registry/bananas.token")).unwrap();
+
+        assert_eq!(
+            args(&Gopass.upsert(&p)),
+            ["gopass", "insert", "-f", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Gopass.edit(&p)),
+            ["gopass", "edit", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Gopass.read(&p)),
+            [
+                "gopass",
+                "show",
+                "--noparsing",
+                "cargo-registry/bananas.token"
+            ]
+        );
+        assert_eq!(
+            args(&Gopass.delete(&p)),
+            ["gopass", "rm", "-f", "cargo-registry/bananas.token"]
+        );
+    }
+
+    #[test]
+    fn test_passage_commands() {
+        let p = PassPath::new(PathBuf::from("cargo-registry/bananas.token")).unwrap();
+
+        assert_eq!(
+            args(&Passage.upsert(&p)),
+            [
+                "passage",
+                "insert",
+                "--multiline",
+                "--force",
+                "cargo-registry/bananas.token"
+            ]
+        );
+        assert_eq!(
+            args(&Passage.edit(&p)),
+            ["passage", "edit", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Passage.read(&p)),
+            ["passage", "show", "cargo-registry/bananas.token"]
+        );
+        assert_eq!(
+            args(&Passage.delete(&p)),
+            ["passage", "rm", "--force", "cargo-registry/bananas.token"]
+        );
+    }
+
+    #[test]
+    fn test_with_extension() {
+        let got = with_extension(PathBuf::from("store/bananas.token"), ".gpg");
+        assert_eq!(got, PathBuf::from("store/bananas.token.gpg"));
+    }
+}
@@ -0,0 +1,280 @@
+use cargo_credential::Secret;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::Error;
+
+/// A password store entry.
+///
+/// Follows the `pass` multi-line convention - the secret is stored on the first
+/// line, and any subsequent lines hold (optional) metadata, typically in the
+/// form of `key: value` pairs:
+///
+/// ```text
+/// cio1234secret
+/// url: https://crates.io/settings/tokens
+/// expires: 2024-12-01T00:00:00Z
+/// ```
+#[derive(Debug)]
+pub(crate) struct Entry {
+    token: Secret<String>,
+
+    /// All lines after the token, verbatim.
+    extra: String,
+}
+
+impl Entry {
+    /// Construct a new [`Entry`] containing `token` and no metadata.
+    pub(crate) fn new(token: &str) -> Self {
+        Self {
+            token: Secret::from(token.to_string()),
+            extra: String::new(),
+        }
+    }
+
+    /// Parse the decrypted contents of an entry.
+    pub(crate) fn parse(s: &str) -> Self {
+        let (token, extra) = s.split_once('\n').unwrap_or((s, ""));
+
+        Self {
+            // Trim any trailing newline / whitespace.
+            token: Secret::from(token.trim().to_string()),
+            extra: extra.to_string(),
+        }
+    }
+
+    /// Replace the token in this entry with `token`, preserving any metadata.
+    ///
+    /// The `expires` metadata describes the replaced token, so it is removed
+    /// if `token` differs.
+    pub(crate) fn with_token(mut self, token: &str) -> Self {
+        if self.token.as_ref().expose() != token {
+            self.remove("expires");
+        }
+
+        Self {
+            token: Secret::from(token.to_string()),
+            ..self
+        }
+    }
+
+    /// Borrow the token stored in this entry.
+    pub(crate) fn token(&self) -> Secret<&str> {
+        self.token.as_deref()
+    }
+
+    /// Return the token stored in this entry.
+    pub(crate) fn into_token(self) -> Secret<String> {
+        self.token
+    }
+
+    /// Render the entry in the format stored in the password store.
+    pub(crate) fn render(&self) -> Secret<String> {
+        Secret::from(format!("{}\n{}", self.token.as_ref().expose(), self.extra))
+    }
+
+    /// Iterate over the `key: value` metadata pairs in this entry.
+    ///
+    /// Lines that are not in the `key: value` form are skipped.
+    pub(crate) fn metadata(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.extra.lines().filter_map(|line| {
+            let (k, v) = line.split_once(':')?;
+            let k = k.trim();
+            if k.is_empty() || k.contains(char::is_whitespace) {
+                return None;
+            }
+            Some((k, v.trim()))
+        })
+    }
+
+    /// Return the value of the first metadata entry with a (case-insensitive)
+    /// key matching `key`.
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.metadata()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Set the metadata `key` to `value`, replacing the first existing entry
+    /// with a (case-insensitive) matching key, or appending it if none exists.
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        let mut replaced = false;
+        let mut extra = String::with_capacity(self.extra.len());
+
+        for line in self.extra.lines() {
+            let matches = line
+                .split_once(':')
+                .is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key));
+
+            if matches && !replaced {
+                replaced = true;
+                extra.push_str(&format!("{key}: {value}\n"));
+            } else {
+                extra.push_str(line);
+                extra.push('\n');
+            }
+        }
+
+        if !replaced {
+            extra.push_str(&format!("{key}: {value}\n"));
+        }
+
+        self.extra = extra;
+    }
+
+    /// Remove all metadata entries with a (case-insensitive) key matching
+    /// `key`.
+    pub(crate) fn remove(&mut self, key: &str) {
+        let mut extra = String::with_capacity(self.extra.len());
+
+        for line in self.extra.lines() {
+            let matches = line
+                .split_once(':')
+                .is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key));
+
+            if !matches {
+                extra.push_str(line);
+                extra.push('\n');
+            }
+        }
+
+        self.extra = extra;
+    }
+
+    /// Return the expiry time of the token from the `expires` metadata, if
+    /// any.
+    ///
+    /// The expiry time may be specified as an RFC 3339 timestamp, or as a unix
+    /// timestamp in seconds.
+    pub(crate) fn expires(&self) -> Result<Option<OffsetDateTime>, Error> {
+        let Some(v) = self.get("expires") else {
+            return Ok(None);
+        };
+
+        let ts = match v.parse::<i64>() {
+            Ok(secs) => OffsetDateTime::from_unix_timestamp(secs).ok(),
+            Err(_) => OffsetDateTime::parse(v, &Rfc3339).ok(),
+        };
+
+        ts.map(Some)
+            .ok_or_else(|| Error::InvalidExpiry(v.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_line() {
+        let e = Entry::parse("bananas\n");
+
+        assert_eq!(e.metadata().count(), 0);
+        assert_eq!(e.render().expose(), "bananas\n");
+        assert_eq!(e.into_token().expose(), "bananas");
+    }
+
+    #[test]
+    fn test_parse_no_trailing_newline() {
+        let e = Entry::parse("  bananas ");
+        assert_eq!(e.into_token().expose(), "bananas");
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let e = Entry::parse(
+            "bananas\n\
+            url: https://itsallbroken.com/tokens\n\
+            Expires: 2024-12-01T00:00:00Z\n\
+            \n\
+            some notes: are here\n\
+            scopes:publish-new\n",
+        );
+
+        assert_eq!(
+            e.metadata().collect::<Vec<_>>(),
+            [
+                ("url", "https://itsallbroken.com/tokens"),
+                ("Expires", "2024-12-01T00:00:00Z"),
+                ("scopes", "publish-new"),
+            ]
+        );
+
+        assert_eq!(e.get("expires"), Some("2024-12-01T00:00:00Z"));
+        assert_eq!(e.get("platanos"), None);
+        assert_eq!(e.into_token().expose(), "bananas");
+    }
+
+    #[test]
+    fn test_with_token_preserves_metadata() {
+        let content = "bananas\nurl: https://itsallbroken.com\n\nnotes go here\n";
+        let e = Entry::parse(content).with_token("platanos");
+
+        assert_eq!(
+            e.render().expose(),
+            "platanos\nurl: https://itsallbroken.com\n\nnotes go here\n"
+        );
+    }
+
+    #[test]
+    fn test_with_token_removes_expiry() {
+        let content = "bananas\nexpires: 2024-12-01T00:00:00Z\nurl: https://itsallbroken.com\n";
+
+        // The expiry of a replaced token no longer applies.
+        let e = Entry::parse(content).with_token("platanos");
+        assert_eq!(
+            e.render().expose(),
+            "platanos\nurl: https://itsallbroken.com\n"
+        );
+        assert_eq!(e.expires().unwrap(), None);
+
+        // Storing the same token again keeps it.
+        let e = Entry::parse(content).with_token("bananas");
+        assert_eq!(e.render().expose(), content);
+    }
+
+    #[test]
+    fn test_expires_rfc3339() {
+        let e = Entry::parse("bananas\nexpires: 2024-12-01T10:00:00+01:00\n");
+        let got = e.expires().expect("valid expiry").expect("has expiry");
+
+        assert_eq!(got.unix_timestamp(), 1733043600);
+    }
+
+    #[test]
+    fn test_expires_unix() {
+        let e = Entry::parse("bananas\nexpires: 1733043600\n");
+        let got = e.expires().expect("valid expiry").expect("has expiry");
+
+        assert_eq!(got.unix_timestamp(), 1733043600);
+    }
+
+    #[test]
+    fn test_expires_none() {
+        let e = Entry::parse("bananas\nurl: https://itsallbroken.com\n");
+        assert!(e.expires().expect("valid expiry").is_none());
+    }
+
+    #[test]
+    fn test_expires_invalid() {
+        let e = Entry::parse("bananas\nexpires: next tuesday\n");
+        assert!(matches!(e.expires(), Err(Error::InvalidExpiry(v)) if v == "next tuesday"));
+    }
+
+    #[test]
+    fn test_set() {
+        let mut e = Entry::parse("bananas\nurl: https://itsallbroken.com\nSubject: old\n");
+
+        e.set("subject", "platanos");
+        e.set("scopes", "publish-new");
+
+        assert_eq!(
+            e.render().expose(),
+            "bananas\nurl: https://itsallbroken.com\nsubject: platanos\nscopes: publish-new\n"
+        );
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Entry::new("bananas").render().expose(), "bananas\n");
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, Output, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::Error;
+
+/// How often a running child process is polled for exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Execute `cmd` to completion, capturing stdout and stderr.
+///
+/// If `stdin` is provided it is written to the child, and the pipe closed to
+/// mark the end of input - otherwise stdin is connected to `/dev/null`, as
+/// our own stdin carries the cargo credential protocol.
+///
+/// If the child does not exit within `timeout` it is killed, and
+/// [`Error::Timeout`] is returned.
+pub(crate) fn run(
+    cmd: &mut Command,
+    stdin: Option<&[u8]>,
+    timeout: Option<Duration>,
+) -> Result<Output, Error> {
+    cmd.stdin(match stdin {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    });
+
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::Exec)?;
+
+    if let Some(input) = stdin {
+        let mut pipe = child.stdin.take().expect("no stdin for child process");
+
+        // Write the input, and close the pipe (by dropping it) to mark the end
+        // of input.
+        pipe.write_all(input).map_err(Error::Exec)?;
+    }
+
+    // Drain the output pipes concurrently, so a child that writes more than
+    // the pipe buffer does not block forever.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = match timeout {
+        Some(timeout) => wait_timeout(&mut child, timeout)?,
+        None => child.wait().map_err(Error::Exec)?,
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Wait for `child` to exit, killing it if it does not exit within `timeout`.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<std::process::ExitStatus, Error> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait().map_err(Error::Exec)? {
+            return Ok(status);
+        }
+
+        if Instant::now() >= deadline {
+            // The output threads are detached, as a grandchild (such as gpg)
+            // may still hold the pipes open.
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Timeout(timeout));
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Read `pipe` to EOF on a background thread.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(script);
+        c
+    }
+
+    #[test]
+    fn test_run() {
+        let got = run(&mut sh("cat; echo bananas >&2"), Some(b"platanos"), None).expect("exec");
+
+        assert!(got.status.success());
+        assert_eq!(got.stdout, b"platanos");
+        assert_eq!(got.stderr, b"bananas\n");
+    }
+
+    #[test]
+    fn test_run_no_stdin() {
+        // The child must not read from our stdin.
+        let got = run(&mut sh("cat"), None, Some(Duration::from_secs(60))).expect("exec");
+
+        assert!(got.status.success());
+        assert!(got.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_run_within_timeout() {
+        let got = run(&mut sh("echo bananas"), None, Some(Duration::from_secs(60))).expect("exec");
+
+        assert!(got.status.success());
+        assert_eq!(got.stdout, b"bananas\n");
+    }
+
+    #[test]
+    fn test_run_timeout() {
+        let start = Instant::now();
+        let got = run(&mut sh("sleep 60"), None, Some(Duration::from_millis(100)));
+
+        assert!(matches!(got, Err(Error::Timeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{io::ErrorKind, path::Path, process::Command, time::Duration};
+
+use cargo_credential::Secret;
+
+use super::{exec, Error};
+
+/// The options that make `gpg` read the key passphrase from stdin, instead of
+/// asking `gpg-agent` to prompt for it with `pinentry`.
+const LOOPBACK_OPTS: [&str; 4] = ["--pinentry-mode", "loopback", "--passphrase-fd", "0"];
+
+/// The env var that enables [`decrypt()`] when set to `1` or `true`.
+pub(crate) const DIRECT_GPG_ENV: &str = "CARGO_CREDENTIAL_PASS_DIRECT_GPG";
+
+/// Returns true if [`DIRECT_GPG_ENV`] enables direct decryption.
+pub(crate) fn direct_gpg_from_env() -> bool {
+    matches!(
+        std::env::var(DIRECT_GPG_ENV).as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// Construct a command that decrypts the password store entry `file` by
+/// invoking `gpg` directly, bypassing `pass`.
+///
+/// Uses the same options as `pass show`, including any user-specified
+/// options in `PASSWORD_STORE_GPG_OPTS`. If `loopback` is set, the key
+/// passphrase is read from stdin rather than prompted for by `pinentry`.
+pub(crate) fn decrypt_cmd(file: &Path, loopback: bool) -> Command {
+    let opts = std::env::var("PASSWORD_STORE_GPG_OPTS").unwrap_or_default();
+
+    let mut cmd = Command::new("gpg");
+    cmd.arg("--decrypt").args(gpg_opts(&opts));
+    if loopback {
+        cmd.args(LOOPBACK_OPTS);
+    }
+    cmd.arg(file);
+    cmd
+}
+
+/// Execute the [`decrypt_cmd()`] `cmd`, killing it if it does not complete
+/// within `timeout`.
+///
+/// The `passphrase` is written to stdin for a loopback [`decrypt_cmd()`].
+///
+/// Returns [`None`] if `gpg` is not installed, in which case the caller should
+/// fall back to reading the entry through `pass`.
+pub(crate) fn decrypt(
+    mut cmd: Command,
+    passphrase: Option<&Secret<String>>,
+    timeout: Option<Duration>,
+) -> Option<Result<Vec<u8>, Error>> {
+    let stdin = passphrase.map(|v| format!("{}\n", v.as_ref().expose()));
+    let output = match exec::run(&mut cmd, stdin.as_deref().map(str::as_bytes), timeout) {
+        Ok(v) => v,
+        Err(Error::Exec(e)) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => return Some(Err(e)),
+    };
+
+    if !output.status.success() {
+        return Some(Err(Error::from_read_output(output)));
+    }
+
+    Some(Ok(output.stdout))
+}
+
+/// Construct the `gpg` options used by `pass show`, prefixed by the
+/// (whitespace separated) `user_opts`.
+///
+/// Unlike `pass show`, `--quiet` is not used - it suppresses the reason a
+/// passphrase could not be prompted for, and stderr is only shown on failure.
+fn gpg_opts(user_opts: &str) -> Vec<&str> {
+    user_opts
+        .split_whitespace()
+        .chain([
+            "--yes",
+            "--compress-algo=none",
+            "--no-encrypt-to",
+            "--batch",
+            "--use-agent",
+        ])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpg_opts() {
+        assert_eq!(
+            gpg_opts(""),
+            [
+                "--yes",
+                "--compress-algo=none",
+                "--no-encrypt-to",
+                "--batch",
+                "--use-agent"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decrypt_cmd_loopback() {
+        let args = |loopback| {
+            decrypt_cmd(Path::new("bananas.gpg"), loopback)
+                .get_args()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(!args(false).iter().any(|v| v == "loopback"));
+
+        let got = args(true);
+        assert_eq!(
+            &got[got.len() - 5..],
+            [
+                "--pinentry-mode",
+                "loopback",
+                "--passphrase-fd",
+                "0",
+                "bananas.gpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gpg_opts_user_specified() {
+        let got = gpg_opts(" --pinentry-mode  loopback ");
+        assert_eq!(&got[..3], ["--pinentry-mode", "loopback", "--yes"]);
+    }
+}
@@ -1,12 +1,16 @@
 use std::{
-    io::Write,
+    cell::OnceCell,
+    ffi::OsString,
+    io::ErrorKind,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    time::Duration,
 };
 
 use cargo_credential::Secret;
 use thiserror::Error;
 
-use super::PassPath;
+use super::{exec, gpg, tty, Backend, Entry, Pass, PassPath};
 
 /// Failures interacting with `pass`.
 #[derive(Debug, Error)]
@@ -15,9 +19,64 @@ pub(crate) enum Error {
     #[error("error executing pass: {0}")]
     Exec(std::io::Error),
 
-    /// `pass` was executed, but returned a non-zero error code..
-    #[error("pass exited with a non-zero status code (stdout='{stdout}', stderr='{stderr}')")]
-    ExecNonZero { stdout: String, stderr: String },
+    /// `pass` was executed, but returned a non-zero error code.
+    ///
+    /// The stdout of commands that read a secret is never captured.
+    #[error(
+        "pass exited with a non-zero status code ({})",
+        exec_output(stdout.as_deref(), stderr)
+    )]
+    ExecNonZero {
+        stdout: Option<String>,
+        stderr: String,
+    },
+
+    /// An interactive `pass` command returned a non-zero exit code.
+    #[error("pass exited with a non-zero status code ({0})")]
+    ExecInteractive(std::process::ExitStatus),
+
+    /// An operation needed to interact with the user, but there is no
+    /// terminal to do so.
+    #[error("no terminal available {0}")]
+    NoTerminal(&'static str),
+
+    /// Reading a token from the terminal failed.
+    #[error("error reading token from terminal: {0}")]
+    Prompt(std::io::Error),
+
+    /// `pass` did not exit within the configured timeout, and was killed.
+    #[error(
+        "pass did not complete within {}s and was killed - it may be waiting for a \
+        pinentry passphrase prompt that cannot be displayed (try setting GPG_TTY, or \
+        unlocking the key first), or for a hardware key such as a YubiKey to be \
+        inserted (configure with timeout=<duration> or CARGO_CREDENTIAL_PASS_TIMEOUT)",
+        .0.as_secs()
+    )]
+    Timeout(Duration),
+
+    /// Listing the entries in the password store failed.
+    #[error("error reading password store: {0}")]
+    ReadStore(std::io::Error),
+
+    /// A token path is absolute, or not valid UTF-8.
+    #[error(
+        "invalid password store path '{}' (must be a relative, UTF-8 path)",
+        .0.display()
+    )]
+    InvalidPath(PathBuf),
+
+    /// A token path template is malformed, or expands to a path outside the
+    /// password store.
+    #[error("invalid token path template '{template}': {reason}")]
+    InvalidTemplate { template: String, reason: String },
+
+    /// The requested entry does not exist in the password store.
+    #[error("{0} is not in the password store")]
+    NotFound(PassPath),
+
+    /// The `expires` metadata of an entry is not a valid timestamp.
+    #[error("invalid expires timestamp '{0}' (expected RFC 3339 or unix timestamp)")]
+    InvalidExpiry(String),
 
     /// The token read from `pass` is not a valid UTF-8 string.
     #[error("read invalid (non-utf8) token: {0}")]
@@ -29,48 +88,220 @@ impl From<std::process::Output> for Error {
         // Success isn't failure.
         assert!(!v.status.success());
 
+        // Diagnostic output may be in any (locale-specific) encoding.
         fn to_err_string(bytes: &[u8]) -> String {
-            std::str::from_utf8(bytes)
-                .expect("invalid utf8 in stderr")
-                .trim_end()
-                .to_string()
+            String::from_utf8_lossy(bytes).trim_end().to_string()
+        }
+
+        let stderr = to_err_string(&v.stderr);
+        if tty::is_pinentry_failure(&stderr) {
+            return Self::NoTerminal(
+                "for gpg to prompt for the key passphrase - unlock the key by running \
+                'pass show' in a terminal first, or set GPG_TTY",
+            );
         }
 
         Self::ExecNonZero {
-            stdout: to_err_string(&v.stdout),
-            stderr: to_err_string(&v.stderr),
+            stdout: Some(to_err_string(&v.stdout)),
+            stderr,
         }
     }
 }
 
+impl Error {
+    /// The placeholder that replaces redacted secrets.
+    const REDACTED: &'static str = "<redacted>";
+
+    /// Construct an error from the failed `output` of a command that reads a
+    /// secret, discarding stdout as it may contain the secret.
+    pub(crate) fn from_read_output(output: std::process::Output) -> Self {
+        match Self::from(output) {
+            Self::ExecNonZero { stderr, .. } => Self::ExecNonZero {
+                stdout: None,
+                stderr,
+            },
+            e => e,
+        }
+    }
+
+    /// Replace any occurrence of `secret` in the command output captured by
+    /// this error.
+    pub(crate) fn redact(self, secret: &str) -> Self {
+        if secret.is_empty() {
+            return self;
+        }
+
+        match self {
+            Self::ExecNonZero { stdout, stderr } => Self::ExecNonZero {
+                stdout: stdout.map(|v| v.replace(secret, Self::REDACTED)),
+                stderr: stderr.replace(secret, Self::REDACTED),
+            },
+            e => e,
+        }
+    }
+}
+
+/// Format the captured output of a failed command for display.
+fn exec_output(stdout: Option<&str>, stderr: &str) -> String {
+    match stdout {
+        Some(stdout) => format!("stdout='{stdout}', stderr='{stderr}'"),
+        None => format!("stderr='{stderr}'"),
+    }
+}
+
 /// [`pass`] integration layer.
 ///
+/// Commands are executed against a [`Backend`], which defaults to [`Pass`].
+///
 /// [`pass`]: https://www.passwordstore.org/
-#[derive(Debug, Default)]
-pub(crate) struct PassKeychain;
+#[derive(Debug)]
+pub(crate) struct PassKeychain {
+    backend: Box<dyn Backend>,
+
+    /// Read tokens by decrypting the store entry with `gpg` directly, where
+    /// the backend supports it.
+    direct_gpg: bool,
+
+    /// Kill non-interactive commands that do not complete within this
+    /// duration.
+    timeout: Option<Duration>,
+
+    /// The store root directory passed to commands, overriding the backend
+    /// default.
+    store_dir: Option<PathBuf>,
+
+    /// The GPG key(s) new entries are encrypted for (`PASSWORD_STORE_KEY`),
+    /// overriding the store `.gpg-id`.
+    store_key: Option<String>,
+
+    /// The GnuPG home directory (`GNUPGHOME`) passed to commands.
+    gnupg_home: Option<PathBuf>,
+
+    /// The (lazily resolved) `GPG_TTY` passed to commands.
+    gpg_tty: OnceCell<Option<OsString>>,
+}
+
+impl Default for PassKeychain {
+    fn default() -> Self {
+        Self::new(Box::new(Pass))
+    }
+}
 
 impl PassKeychain {
+    /// Initialise a [`PassKeychain`] that executes commands against
+    /// `backend`.
+    pub(crate) fn new(backend: Box<dyn Backend>) -> Self {
+        Self {
+            backend,
+            direct_gpg: false,
+            timeout: None,
+            store_dir: None,
+            store_key: None,
+            gnupg_home: None,
+            gpg_tty: OnceCell::new(),
+        }
+    }
+
+    /// Read tokens by invoking `gpg` directly instead of spawning the backend
+    /// CLI (which in turn spawns `gpg`) when `enabled`.
+    ///
+    /// The backend CLI is still used if the entry cannot be decrypted
+    /// directly, such as when using a non-GPG backend, or `gpg` is not
+    /// installed.
+    pub(crate) fn with_direct_gpg(mut self, enabled: bool) -> Self {
+        self.direct_gpg = enabled;
+        self
+    }
+
+    /// Kill any command (other than [`PassKeychain::edit_token()`], which waits
+    /// for the user) that does not complete within `timeout`.
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Use the store rooted at `dir` instead of the backend default (typically
+    /// set by the `PASSWORD_STORE_DIR` env var), if provided.
+    ///
+    /// Token paths remain relative to the store root.
+    pub(crate) fn with_store_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.store_dir = dir;
+        self
+    }
+
+    /// Encrypt new entries for the GPG `key` instead of the recipients in the
+    /// store `.gpg-id`, if provided.
+    pub(crate) fn with_store_key(mut self, key: Option<String>) -> Self {
+        self.store_key = key;
+        self
+    }
+
+    /// Run commands (including direct `gpg` decryption) with the GnuPG home
+    /// directory `dir`, if provided.
+    pub(crate) fn with_gnupg_home(mut self, dir: Option<PathBuf>) -> Self {
+        self.gnupg_home = dir;
+        self
+    }
+
+    /// Return the root directory of the password store.
+    pub(crate) fn store_dir(&self) -> PathBuf {
+        self.store_dir
+            .clone()
+            .unwrap_or_else(|| self.backend.store_dir())
+    }
+
     /// Insert or overwrite the `path` to store `token`.
+    ///
+    /// If an entry already exists at `path`, any metadata lines following the
+    /// token are preserved.
     pub(crate) fn upsert_token(&self, path: &PassPath, token: &Secret<&str>) -> Result<(), Error> {
-        let mut child = Command::new("pass")
-            .arg("insert")
-            .arg("--force")
-            .arg(path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::inherit())
-            .spawn()
-            .map_err(Error::Exec)?;
+        self.upsert_token_with(path, token, &[])
+    }
 
-        let mut stdin = child.stdin.take().expect("no stdin for pass child process");
+    /// Insert or overwrite the `path` to store `token`, setting the specified
+    /// `metadata` key/value pairs.
+    ///
+    /// Any other metadata lines in an existing entry at `path` are preserved.
+    /// If the existing entry cannot be read (such as after the key it is
+    /// encrypted for is lost) a warning is printed, and it is replaced without
+    /// its metadata.
+    pub(crate) fn upsert_token_with(
+        &self,
+        path: &PassPath,
+        token: &Secret<&str>,
+        metadata: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        let existing = match self.may_contain(path) {
+            true => self.read_entry(path),
+            false => Err(Error::NotFound(path.clone())),
+        };
 
-        // Write the token to stdin.
-        writeln!(stdin, "{}", token.as_ref().expose()).map_err(Error::Exec)?;
+        let mut entry = match existing {
+            Ok(v) => v.with_token(token.as_ref().expose()),
+            Err(Error::NotFound(_)) => Entry::new(token.as_ref().expose()),
+            Err(e) => {
+                eprintln!(
+                    "warning: replacing unreadable token in {path}, dropping any metadata: {e}"
+                );
+                Entry::new(token.as_ref().expose())
+            }
+        };
 
-        // Write the repeat / confirmation.
-        writeln!(stdin, "{}", token.as_ref().expose()).map_err(Error::Exec)?;
+        for (k, v) in metadata {
+            entry.set(k, v);
+        }
 
-        // And wait for pass to exit.
-        let output = child.wait_with_output().map_err(Error::Exec)?;
+        self.write_entry(path, &entry)
+            .map_err(|e| e.redact(token.as_ref().expose()))
+    }
+
+    /// Insert or overwrite the `path` to store `entry`.
+    fn write_entry(&self, path: &PassPath, entry: &Entry) -> Result<(), Error> {
+        let output = exec::run(
+            &mut self.with_env(self.backend.upsert(path)),
+            Some(entry.render().expose().as_bytes()),
+            self.timeout,
+        )?;
 
         if !output.status.success() {
             return Err(Error::from(output));
@@ -80,15 +311,24 @@ impl PassKeychain {
     }
 
     /// Pop up the user's `$EDITOR` to edit the token at `path`.
+    ///
+    /// The editor is attached to the controlling terminal, as stdin and stdout
+    /// are used for the cargo credential protocol.
     pub(crate) fn edit_token(&self, path: &PassPath) -> Result<(), Error> {
-        let output = Command::new("pass")
-            .arg("edit")
-            .arg(path)
-            .output()
+        let tty = tty::open().ok_or(Error::NoTerminal(
+            "to open an editor - provide the token with 'cargo login <token>' instead",
+        ))?;
+
+        let status = self
+            .with_env(self.backend.edit(path))
+            .stdin(Stdio::from(tty.try_clone().map_err(Error::Exec)?))
+            .stdout(Stdio::from(tty.try_clone().map_err(Error::Exec)?))
+            .stderr(Stdio::from(tty))
+            .status()
             .map_err(Error::Exec)?;
 
-        if !output.status.success() {
-            return Err(Error::from(output));
+        if !status.success() {
+            return Err(Error::ExecInteractive(status));
         }
 
         Ok(())
@@ -96,37 +336,308 @@ impl PassKeychain {
 
     /// Delete the token at `path`.
     pub(crate) fn delete_token(&self, path: &PassPath) -> Result<(), Error> {
-        let output = Command::new("pass")
-            .arg("rm")
-            .arg("--force")
-            .arg(path)
-            .output()
-            .map_err(Error::Exec)?;
+        let output = exec::run(
+            &mut self.with_env(self.backend.delete(path)),
+            None,
+            self.timeout,
+        )?;
 
         if !output.status.success() {
-            return Err(Error::from(output));
+            return Err(self.missing_or(path, Error::from(output)));
         }
 
         Ok(())
     }
 
-    /// Read the token at `path`.
-    pub(crate) fn read_token(&self, path: &PassPath) -> Result<Secret<String>, Error> {
-        let output = Command::new("pass")
-            .arg("show")
-            .arg(path)
-            .output()
-            .map_err(Error::Exec)?;
+    /// Read the entry at `path`, containing the token and any metadata.
+    pub(crate) fn read_entry(&self, path: &PassPath) -> Result<Entry, Error> {
+        let stdout = match self.read_gpg(path) {
+            Some(v) => v?,
+            None => {
+                let output = exec::run(
+                    &mut self.with_env(self.backend.read(path)),
+                    None,
+                    self.timeout,
+                )?;
 
-        if !output.status.success() {
-            return Err(Error::from(output));
+                if !output.status.success() {
+                    return Err(self.missing_or(path, Error::from_read_output(output)));
+                }
+
+                output.stdout
+            }
+        };
+
+        Ok(Entry::parse(&String::from_utf8(stdout)?))
+    }
+
+    /// Attempt to decrypt the entry at `path` with `gpg` directly, returning
+    /// [`None`] if this is disabled or not possible.
+    ///
+    /// If `pinentry` cannot prompt for the key passphrase but there is a
+    /// controlling terminal, the passphrase is prompted for on the terminal and
+    /// passed to `gpg` with loopback pinentry.
+    fn read_gpg(&self, path: &PassPath) -> Option<Result<Vec<u8>, Error>> {
+        if !self.direct_gpg {
+            return None;
+        }
+
+        let file = self
+            .backend
+            .gpg_file(&self.store_dir(), path)
+            .filter(|v| v.is_file())?;
+
+        match gpg::decrypt(
+            self.with_env(gpg::decrypt_cmd(&file, false)),
+            None,
+            self.timeout,
+        )? {
+            // pinentry cannot reach the terminal, so prompt for the passphrase
+            // on it directly and pass it to gpg in loopback mode.
+            Err(Error::NoTerminal(_)) if tty::open().is_some() => {
+                let passphrase = match tty::prompt_secret(&format!("passphrase for {path}: ")) {
+                    Ok(v) => v,
+                    Err(e) => return Some(Err(e)),
+                };
+                gpg::decrypt(
+                    self.with_env(gpg::decrypt_cmd(&file, true)),
+                    Some(&passphrase),
+                    self.timeout,
+                )
+            }
+            v => Some(v),
         }
+    }
+
+    /// List the tokens (entries with a `.token` suffix) stored under `dir`,
+    /// relative to the password store root.
+    ///
+    /// Returns an empty list if `dir` does not exist.
+    pub(crate) fn list_tokens(&self, dir: &Path) -> Result<Vec<PassPath>, Error> {
+        let root = self.store_dir();
+        let suffix = format!(".token{}", self.backend.extension());
+
+        let mut out = Vec::new();
+        let mut pending = vec![root.join(dir)];
 
-        let token = String::from_utf8(output.stdout)?;
+        while let Some(dir) = pending.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(v) => v,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::ReadStore(e)),
+            };
 
-        // Trim any trailing newline / whitespace.
-        let token = token.trim();
+            for entry in entries {
+                let path = entry.map_err(Error::ReadStore)?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let Some(name) = path.to_str().and_then(|v| v.strip_suffix(&suffix)) else {
+                    continue;
+                };
+
+                // Strip the store root and encrypted file extension.
+                let Ok(rel) = Path::new(name).strip_prefix(&root) else {
+                    continue;
+                };
+
+                let mut rel = rel.to_path_buf();
+                rel.as_mut_os_string().push(".token");
+                out.push(PassPath::new(rel)?);
+            }
+        }
 
-        Ok(Secret::from(token.to_string()))
+        out.sort_by_key(|v| v.to_string());
+        Ok(out)
+    }
+
+    /// Set the store root directory, key and GnuPG home (if configured) for
+    /// `cmd`, and `GPG_TTY` so that `pinentry` can prompt for a passphrase on
+    /// the controlling terminal, if any.
+    fn with_env(&self, mut cmd: Command) -> Command {
+        if let Some(dir) = &self.store_dir {
+            cmd.env(self.backend.store_dir_env(), dir);
+        }
+        if let Some(key) = &self.store_key {
+            cmd.env("PASSWORD_STORE_KEY", key);
+        }
+        if let Some(dir) = &self.gnupg_home {
+            cmd.env("GNUPGHOME", dir);
+        }
+        if let Some(v) = self.gpg_tty.get_or_init(tty::gpg_tty) {
+            cmd.env("GPG_TTY", v);
+        }
+        cmd
+    }
+
+    /// Returns true if an entry for `path` exists in the password store.
+    pub(crate) fn contains(&self, path: &PassPath) -> bool {
+        self.backend.entry_file(&self.store_dir(), path).is_file()
+    }
+
+    /// Returns false if the entry for `path` is known not to exist, without
+    /// invoking the backend CLI.
+    ///
+    /// Entries of a backend without a [fixed layout](Backend::fixed_layout)
+    /// may exist anywhere, so this is always true for them.
+    pub(crate) fn may_contain(&self, path: &PassPath) -> bool {
+        !self.backend.fixed_layout() || self.contains(path)
+    }
+
+    /// Classify the failure `err` of an operation against `path`.
+    ///
+    /// If the entry is not in the password store, [`Error::NotFound`] is
+    /// returned, otherwise `err` is returned.
+    fn missing_or(&self, path: &PassPath, err: Error) -> Error {
+        if !self.contains(path) {
+            return Error::NotFound(path.clone());
+        }
+        err
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn output(stdout: impl AsRef<[u8]>, stderr: impl AsRef<[u8]>) -> std::process::Output {
+        std::process::Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: stdout.as_ref().to_vec(),
+            stderr: stderr.as_ref().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_error_from_output() {
+        let got = Error::from(output("platanos\n", "oh no\n"));
+        assert_eq!(
+            got.to_string(),
+            "pass exited with a non-zero status code (stdout='platanos', stderr='oh no')"
+        );
+    }
+
+    #[test]
+    fn test_error_from_pinentry_failure() {
+        let got = Error::from_read_output(output(
+            "",
+            "gpg: public key decryption failed: Inappropriate ioctl for device\n",
+        ));
+        assert!(matches!(got, Error::NoTerminal(_)));
+    }
+
+    #[test]
+    fn test_error_from_read_output() {
+        let got = Error::from_read_output(output("bananas\n", "oh no\n"));
+        assert_eq!(
+            got.to_string(),
+            "pass exited with a non-zero status code (stderr='oh no')"
+        );
+    }
+
+    #[test]
+    fn test_error_redact() {
+        let got = Error::from(output("bananas\nbananas", "bad token bananas")).redact("bananas");
+        assert_eq!(
+            got.to_string(),
+            "pass exited with a non-zero status code \
+            (stdout='<redacted>\n<redacted>', stderr='bad token <redacted>')"
+        );
+
+        // Empty secrets are not redacted.
+        let got = Error::from(output("", "oh no")).redact("");
+        assert_eq!(
+            got.to_string(),
+            "pass exited with a non-zero status code (stdout='', stderr='oh no')"
+        );
+    }
+
+    #[test]
+    fn test_error_timeout() {
+        let got = Error::Timeout(Duration::from_secs(30)).to_string();
+        assert!(got.starts_with("pass did not complete within 30s and was killed"));
+        assert!(
+            got.ends_with("(configure with timeout=<duration> or CARGO_CREDENTIAL_PASS_TIMEOUT)")
+        );
+    }
+
+    #[test]
+    fn test_error_from_non_utf8_output() {
+        // A Latin-1 encoded "échec".
+        let got = Error::from(output(b"", b"\xe9chec"));
+        assert_eq!(
+            got.to_string(),
+            "pass exited with a non-zero status code (stdout='', stderr='\u{FFFD}chec')"
+        );
+    }
+
+    #[test]
+    fn test_with_env() {
+        let env = |keychain: &PassKeychain, key: &str| {
+            let cmd = keychain.with_env(Command::new("pass"));
+            cmd.get_envs()
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| v.map(ToOwned::to_owned))
+        };
+
+        // Nothing is overridden by default.
+        let keychain = PassKeychain::default();
+        assert_eq!(env(&keychain, "PASSWORD_STORE_DIR"), None);
+        assert_eq!(env(&keychain, "PASSWORD_STORE_KEY"), None);
+        assert_eq!(env(&keychain, "GNUPGHOME"), None);
+
+        let keychain = PassKeychain::default()
+            .with_store_dir(Some(PathBuf::from("/srv/store")))
+            .with_store_key(Some("bananas@itsallbroken.com".to_string()))
+            .with_gnupg_home(Some(PathBuf::from("/srv/gnupg")));
+
+        assert_eq!(keychain.store_dir(), PathBuf::from("/srv/store"));
+        assert_eq!(
+            env(&keychain, "PASSWORD_STORE_DIR"),
+            Some(OsString::from("/srv/store"))
+        );
+        assert_eq!(
+            env(&keychain, "PASSWORD_STORE_KEY"),
+            Some(OsString::from("bananas@itsallbroken.com"))
+        );
+        assert_eq!(
+            env(&keychain, "GNUPGHOME"),
+            Some(OsString::from("/srv/gnupg"))
+        );
+    }
+
+    proptest! {
+        /// A redacted error never displays the secret, wherever it appears in
+        /// the (possibly non-UTF-8) command output.
+        #[test]
+        fn prop_error_redact(
+            secret in "[a-z0-9]{12}",
+            stdout in proptest::collection::vec(any::<Vec<u8>>(), 1..4),
+            stderr in proptest::collection::vec(any::<Vec<u8>>(), 1..4),
+        ) {
+            let stdout = stdout.join(secret.as_bytes());
+            let stderr = stderr.join(secret.as_bytes());
+
+            let got = Error::from(output(&stdout, &stderr)).redact(&secret).to_string();
+            prop_assert!(!got.contains(&secret), "{got}");
+        }
+
+        /// The output of a command that reads a secret is never displayed.
+        #[test]
+        fn prop_error_from_read_output_hides_stdout(
+            token in "[a-z0-9]{12}",
+            stderr in any::<Vec<u8>>(),
+        ) {
+            prop_assume!(!String::from_utf8_lossy(&stderr).contains(&token));
+
+            let got = Error::from_read_output(output(&token, &stderr)).to_string();
+            prop_assert!(!got.contains(&token), "{got}");
+        }
     }
 }
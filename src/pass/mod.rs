@@ -1,7 +1,16 @@
 //! `pass` integration.
 
+mod backend;
+mod entry;
+mod exec;
+mod gpg;
 mod keychain;
 mod path;
+mod tty;
 
+pub(crate) use backend::*;
+pub(crate) use entry::*;
+pub(crate) use gpg::direct_gpg_from_env;
 pub(crate) use keychain::*;
 pub(crate) use path::*;
+pub(crate) use tty::prompt_secret;
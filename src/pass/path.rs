@@ -1,10 +1,171 @@
 use std::{
-    ffi::{OsStr, OsString},
+    ffi::OsStr,
     fmt::Display,
     path::{Path, PathBuf},
 };
 
-use cargo_credential::RegistryInfo;
+use cargo_credential::{Operation, RegistryInfo};
+use sha2::{Digest, Sha256};
+
+use super::Error;
+
+/// The operations that may have a dedicated token, used in preference to the
+/// registry's generic token.
+pub(crate) const OPERATIONS: [&str; 3] = ["publish", "yank", "owners"];
+
+/// Return the name used for `op` in operation-specific token paths, or
+/// [`None`] if `op` always uses the generic token.
+///
+/// Yanking and unyanking a crate share the same token.
+pub(crate) fn operation_name(op: &Operation<'_>) -> Option<&'static str> {
+    match op {
+        Operation::Publish { .. } => Some("publish"),
+        Operation::Yank { .. } | Operation::Unyank { .. } => Some("yank"),
+        Operation::Owners { .. } => Some("owners"),
+        _ => None,
+    }
+}
+
+/// Return the name of the crate `op` acts on, if any.
+pub(crate) fn crate_name<'a>(op: &Operation<'a>) -> Option<&'a str> {
+    let name = match op {
+        Operation::Publish { name, .. }
+        | Operation::Yank { name, .. }
+        | Operation::Unyank { name, .. }
+        | Operation::Owners { name } => name,
+        _ => return None,
+    };
+    Some(*name).filter(|v| !v.is_empty())
+}
+
+/// The placeholders that may be used in a token path template.
+pub(crate) const PLACEHOLDERS: [&str; 6] =
+    ["name", "registry", "host", "url", "operation", "crate"];
+
+/// The number of bytes of the URL hash used in derived token names.
+const URL_HASH_BYTES: usize = 8;
+
+/// The value of the `{operation}` and `{crate}` placeholders when the token is
+/// not specific to an operation or crate.
+const DEFAULT_PLACEHOLDER: &str = "default";
+
+/// The values substituted into a token path template by [`expand_template()`].
+#[derive(Debug, Default)]
+pub(crate) struct Placeholders<'a> {
+    /// The registry name, if known.
+    pub(crate) name: Option<&'a str>,
+
+    /// The registry index URL.
+    pub(crate) index_url: &'a str,
+
+    /// The operation the token is specific to, if any.
+    pub(crate) operation: Option<&'a str>,
+
+    /// The crate the token is specific to, if any.
+    pub(crate) crate_name: Option<&'a str>,
+}
+
+impl Placeholders<'_> {
+    /// Return the value of the placeholder `key`, or [`None`] if `key` is not
+    /// one of [`PLACEHOLDERS`].
+    fn get(&self, key: &str) -> Option<Result<String, String>> {
+        let url = || {
+            // As for derived token names, the index protocol is ignored.
+            self.index_url
+                .strip_prefix("sparse+")
+                .unwrap_or(self.index_url)
+        };
+
+        let v = match key {
+            // The derived name of an unnamed registry is unique to the URL,
+            // flattened into a single path component.
+            "name" | "registry" => Ok(self
+                .name
+                .map(ToString::to_string)
+                .unwrap_or_else(|| derive_name(url()).replace('/', "_"))),
+            "host" => host(url())
+                .map(str::to_ascii_lowercase)
+                .ok_or_else(|| format!("index URL '{}' has no host", self.index_url)),
+            "url" => Ok(normalise_url(url())),
+            "operation" => Ok(self.operation.unwrap_or(DEFAULT_PLACEHOLDER).to_string()),
+            "crate" => Ok(self.crate_name.unwrap_or(DEFAULT_PLACEHOLDER).to_string()),
+            _ => return None,
+        };
+
+        Some(v)
+    }
+}
+
+/// Returns true if `path` is a template containing `{placeholder}`s.
+pub(crate) fn is_template(path: &str) -> bool {
+    path.contains(['{', '}'])
+}
+
+/// Expand the `{placeholder}`s in the token path `template` (one of
+/// [`PLACEHOLDERS`]) with the values in `v`.
+///
+/// Returns [`Error::InvalidTemplate`] if the template is malformed, uses an
+/// unknown placeholder, or expands to a path that is absolute or contains `..`
+/// components - an expanded template is always within the password store.
+pub(crate) fn expand_template(template: &str, v: &Placeholders<'_>) -> Result<String, Error> {
+    let invalid = |reason: String| Error::InvalidTemplate {
+        template: template.to_string(),
+        reason,
+    };
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(invalid("unmatched '}'".to_string()));
+        }
+        out.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| invalid("unmatched '{'".to_string()))?;
+        let key = &rest[start + 1..start + end];
+
+        let value = v
+            .get(key)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "unknown placeholder '{{{key}}}' (expected one of: {})",
+                    PLACEHOLDERS.map(|v| format!("{{{v}}}")).join(", ")
+                ))
+            })?
+            .map_err(&invalid)?;
+
+        // A value must only ever fill in (part of) a single path component.
+        if value.contains('/') {
+            return Err(invalid(format!(
+                "value '{value}' of placeholder '{{{key}}}' contains '/'"
+            )));
+        }
+
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    if out.starts_with('/') {
+        return Err(invalid(format!("expands to absolute path '{out}'")));
+    }
+    if out.split('/').any(|v| v == "..") {
+        return Err(invalid(format!("expands to '{out}' containing '..'")));
+    }
+
+    Ok(out)
+}
+
+/// Return the host (and port, if any) of `url`, without any user info.
+fn host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, v)| v);
+    Some(host).filter(|v| !v.is_empty())
+}
 
 /// An initialiser of [`PassPath`] from configuration.
 #[derive(Debug, Default)]
@@ -14,6 +175,13 @@ pub(crate) struct PassPathBuilder<'a, 'b> {
 
     /// Filename without extension.
     name: Option<&'b str>,
+
+    /// The operation the token is specific to, if any.
+    operation: Option<&'static str>,
+
+    /// Derive the name of an unnamed registry using the (collision-prone)
+    /// normalised URL scheme of earlier releases.
+    legacy_name: bool,
 }
 
 impl<'a, 'b> PassPathBuilder<'a, 'b> {
@@ -42,14 +210,36 @@ impl<'a, 'b> PassPathBuilder<'a, 'b> {
         self
     }
 
+    /// Address a token specific to `operation` (one of [`OPERATIONS`]), stored
+    /// alongside the registry's generic token as `<name>.<operation>.token`.
+    pub(crate) fn for_operation(mut self, operation: &'static str) -> Self {
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Derive the name of an unnamed registry as earlier releases did, by
+    /// replacing all non-alphanumeric characters in the index URL with `_`.
+    ///
+    /// This is only used to find tokens stored by earlier releases - distinct
+    /// URLs may map to the same legacy name.
+    pub(crate) fn with_legacy_name(mut self) -> Self {
+        self.legacy_name = true;
+        self
+    }
+
     /// Instantiate a [`PassPath`] with the specified components.
-    pub(crate) fn build(self, index_url: &str) -> PassPath {
+    ///
+    /// Returns [`Error::InvalidPath`] if the resulting path is not valid UTF-8.
+    pub(crate) fn build(self, index_url: &str) -> Result<PassPath, Error> {
         let name = self.name.map(ToString::to_string).unwrap_or_else(|| {
             // Remove the sparse+ prefix from the URL if present, so that a
             // token is resolved for a registry regardless of the index protocol
             // used.
             let name = index_url.strip_prefix("sparse+").unwrap_or(index_url);
-            normalise_url(name)
+            match self.legacy_name {
+                true => normalise_url(name),
+                false => derive_name(name),
+            }
         });
 
         let mut path = self.dir.map(ToOwned::to_owned).unwrap_or_else(|| {
@@ -58,46 +248,56 @@ impl<'a, 'b> PassPathBuilder<'a, 'b> {
             p
         });
 
-        path.push(format!("{}.token", name));
+        match self.operation {
+            Some(op) => path.push(format!("{}.{}.token", name, op)),
+            None => path.push(format!("{}.token", name)),
+        }
 
         PassPath::new(path)
     }
 }
 
 /// A `pass` path used to deterministically address a specific registry.
-#[derive(Debug)]
-pub(crate) struct PassPath(OsString);
+///
+/// Always a relative, UTF-8 path.
+#[derive(Debug, Clone)]
+pub(crate) struct PassPath(String);
 
 impl PassPath {
     /// Initialise a new [`PassPath`] instance that stores the token in the
     /// specified path.
     ///
-    /// # Panics
-    ///
-    /// This call panics if an absolute path is provided. A relative path must
-    /// be provided as the path will be interpreted relative to the password
-    /// store root.
-    pub(crate) fn new(path: PathBuf) -> Self {
-        assert!(path.is_relative());
+    /// Returns [`Error::InvalidPath`] if `path` is absolute, or is not valid
+    /// UTF-8. A relative path must be provided as the path will be interpreted
+    /// relative to the password store root.
+    pub(crate) fn new(path: PathBuf) -> Result<Self, Error> {
+        if !path.is_relative() {
+            return Err(Error::InvalidPath(path));
+        }
 
-        Self(OsString::from(path))
+        path.into_os_string()
+            .into_string()
+            .map(Self)
+            .map_err(|v| Error::InvalidPath(PathBuf::from(v)))
     }
 }
 
 impl Display for PassPath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.to_str().expect("invalid registry name").fmt(f)
+        self.0.fmt(f)
     }
 }
 
 impl AsRef<OsStr> for PassPath {
     fn as_ref(&self) -> &OsStr {
-        &self.0
+        self.0.as_ref()
     }
 }
 
-impl<'a> From<&'a RegistryInfo<'a>> for PassPath {
-    fn from(v: &'a RegistryInfo<'a>) -> Self {
+impl<'a> TryFrom<&'a RegistryInfo<'a>> for PassPath {
+    type Error = Error;
+
+    fn try_from(v: &'a RegistryInfo<'a>) -> Result<Self, Self::Error> {
         let mut p = PassPathBuilder::default();
 
         // Use the provided name or infer one if not specified.
@@ -109,6 +309,32 @@ impl<'a> From<&'a RegistryInfo<'a>> for PassPath {
     }
 }
 
+/// Derive a token name for the registry at `url` that is unique to the URL -
+/// the short, stable hash of the full URL, under a directory named after the
+/// index host (if any) so tokens remain easy to identify.
+fn derive_name(url: &str) -> String {
+    let hash = Sha256::digest(url.as_bytes())[..URL_HASH_BYTES]
+        .iter()
+        .map(|v| format!("{v:02x}"))
+        .collect::<String>();
+
+    match host(url).and_then(normalise_host) {
+        Some(host) => format!("{host}/{hash}"),
+        None => hash,
+    }
+}
+
+/// Replace any characters in `host` other than alphanumerics, `.` and `-` with
+/// an underscore, returning [`None`] if the result is not a usable directory
+/// name.
+fn normalise_host(host: &str) -> Option<String> {
+    let host = host.to_ascii_lowercase().replace(
+        |v: char| !(v.is_alphanumeric() || v == '.' || v == '-'),
+        "_",
+    );
+    Some(host).filter(|v| v != "." && v != "..")
+}
+
 /// Replace any non-alphanumeric characters in `url` with an underscore.
 fn normalise_url(url: &str) -> String {
     url.replace(|v| !char::is_alphanumeric(v), "_")
@@ -116,35 +342,86 @@ fn normalise_url(url: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    use proptest::prelude::*;
+
     use super::*;
 
     #[test]
     fn test_path_with_name() {
         let p = PassPathBuilder::default()
             .with_name("bananas")
-            .build("sparse+https://cargo.itsallbroken.com/index/");
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(p.to_string(), "cargo-registry/bananas.token");
     }
 
     #[test]
     fn test_path_without_name_sparse() {
-        let p = PassPathBuilder::default().build("sparse+https://cargo.itsallbroken.com/index/");
+        let p = PassPathBuilder::default()
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(
             p.to_string(),
-            "cargo-registry/https___cargo_itsallbroken_com_index_.token"
+            "cargo-registry/cargo.itsallbroken.com/3621eec7e6b038b9.token"
         );
     }
 
     #[test]
     fn test_path_without_name_non_sparse() {
-        let p = PassPathBuilder::default().build("https://cargo.itsallbroken.com/index/");
+        let p = PassPathBuilder::default()
+            .build("https://cargo.itsallbroken.com/index/")
+            .unwrap();
+
+        assert_eq!(
+            p.to_string(),
+            "cargo-registry/cargo.itsallbroken.com/3621eec7e6b038b9.token"
+        );
+    }
+
+    #[test]
+    fn test_path_without_name_distinct() {
+        let path = |url| PassPathBuilder::default().build(url).unwrap().to_string();
+
+        // URLs that normalise to the same legacy name derive distinct paths.
+        assert_eq!(
+            path("https://a-b.com/x"),
+            "cargo-registry/a-b.com/170c44ca4603f23c.token"
+        );
+        assert_eq!(
+            path("https://a_b.com/x"),
+            "cargo-registry/a_b.com/3947c7ed41f18a7e.token"
+        );
+
+        // A URL without a host is named by the hash alone.
+        assert_eq!(path("bananas"), "cargo-registry/e4ba5cbd251c98e6.token");
+        assert_eq!(path("https://../"), "cargo-registry/67eb26e8fb70c681.token");
+    }
+
+    #[test]
+    fn test_path_legacy_name() {
+        let p = PassPathBuilder::default()
+            .with_legacy_name()
+            .for_operation("yank")
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(
             p.to_string(),
-            "cargo-registry/https___cargo_itsallbroken_com_index_.token"
+            "cargo-registry/https___cargo_itsallbroken_com_index_.yank.token"
         );
+
+        // Registry names are used as-is.
+        let p = PassPathBuilder::default()
+            .with_legacy_name()
+            .with_name("bananas")
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
+
+        assert_eq!(p.to_string(), "cargo-registry/bananas.token");
     }
 
     #[test]
@@ -152,7 +429,8 @@ mod tests {
         let p = PassPathBuilder::default()
             .with_name("bananas")
             .under_dir(Path::new("platanos/are/good"))
-            .build("sparse+https://cargo.itsallbroken.com/index/");
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(p.to_string(), "platanos/are/good/bananas.token");
     }
@@ -162,19 +440,287 @@ mod tests {
         let p = PassPathBuilder::default()
             .with_name("bananas")
             .under_dir(Path::new("platanos/are/good/"))
-            .build("sparse+https://cargo.itsallbroken.com/index/");
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(p.to_string(), "platanos/are/good/bananas.token");
     }
 
+    #[test]
+    fn test_path_for_operation() {
+        let p = PassPathBuilder::default()
+            .with_name("bananas")
+            .for_operation("publish")
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
+
+        assert_eq!(p.to_string(), "cargo-registry/bananas.publish.token");
+
+        let p = PassPathBuilder::default()
+            .for_operation("yank")
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
+
+        assert_eq!(
+            p.to_string(),
+            "cargo-registry/cargo.itsallbroken.com/3621eec7e6b038b9.yank.token"
+        );
+    }
+
+    #[test]
+    fn test_operation_name() {
+        assert_eq!(operation_name(&Operation::Read), None);
+        assert_eq!(
+            operation_name(&Operation::Publish {
+                name: "bananas",
+                vers: "1.0.0",
+                cksum: "42"
+            }),
+            Some("publish")
+        );
+        assert_eq!(
+            operation_name(&Operation::Yank {
+                name: "bananas",
+                vers: "1.0.0"
+            }),
+            Some("yank")
+        );
+        assert_eq!(
+            operation_name(&Operation::Unyank {
+                name: "bananas",
+                vers: "1.0.0"
+            }),
+            Some("yank")
+        );
+        assert_eq!(
+            operation_name(&Operation::Owners { name: "bananas" }),
+            Some("owners")
+        );
+    }
+
+    #[test]
+    fn test_crate_name() {
+        assert_eq!(crate_name(&Operation::Read), None);
+        assert_eq!(
+            crate_name(&Operation::Publish {
+                name: "bananas",
+                vers: "1.0.0",
+                cksum: "42"
+            }),
+            Some("bananas")
+        );
+        assert_eq!(
+            crate_name(&Operation::Owners { name: "platanos" }),
+            Some("platanos")
+        );
+    }
+
+    const PLACEHOLDERS_REG: Placeholders<'static> = Placeholders {
+        name: Some("bananas"),
+        index_url: "sparse+https://user@Cargo.ItsAllBroken.com:4242/index/",
+        operation: Some("publish"),
+        crate_name: Some("platanos"),
+    };
+
+    #[test]
+    fn test_expand_template() {
+        let expand = |v: &str| expand_template(v, &PLACEHOLDERS_REG).expect("valid template");
+
+        assert_eq!(
+            expand("cargo/{host}/{name}.token"),
+            "cargo/cargo.itsallbroken.com:4242/bananas.token"
+        );
+        assert_eq!(
+            expand("tokens/{registry}/{operation}"),
+            "tokens/bananas/publish"
+        );
+        assert_eq!(
+            expand("{url}/{crate}.token"),
+            "https___user_Cargo_ItsAllBroken_com_4242_index_/platanos.token"
+        );
+        assert_eq!(expand("no/placeholders"), "no/placeholders");
+    }
+
+    #[test]
+    fn test_expand_template_defaults() {
+        let v = Placeholders {
+            index_url: "https://cargo.itsallbroken.com/index/",
+            ..Default::default()
+        };
+
+        assert_eq!(
+            expand_template("{name}/{operation}/{crate}", &v).unwrap(),
+            "cargo.itsallbroken.com_3621eec7e6b038b9/default/default"
+        );
+    }
+
+    #[test]
+    fn test_expand_template_unnamed_collision() {
+        // Distinct URLs with the same normalised form expand to distinct
+        // names.
+        let expand = |index_url: &str| {
+            let v = Placeholders {
+                index_url,
+                ..Default::default()
+            };
+            (
+                expand_template("tokens/{name}.token", &v).unwrap(),
+                expand_template("tokens/{registry}.token", &v).unwrap(),
+            )
+        };
+
+        let a = expand("https://itsallbroken.com/b_c");
+        let b = expand("https://itsallbroken.com/b/c");
+        assert_ne!(a.0, b.0);
+        assert_ne!(a.1, b.1);
+        assert_eq!(a.0, a.1);
+        assert!(a.0.starts_with("tokens/itsallbroken.com_"));
+    }
+
+    #[test]
+    fn test_expand_template_invalid() {
+        let err = |template: &str, v: &Placeholders<'_>| {
+            expand_template(template, v)
+                .expect_err("invalid template")
+                .to_string()
+        };
+
+        assert_eq!(
+            err("tokens/{bananas}", &PLACEHOLDERS_REG),
+            "invalid token path template 'tokens/{bananas}': unknown placeholder '{bananas}' \
+            (expected one of: {name}, {registry}, {host}, {url}, {operation}, {crate})"
+        );
+        assert_eq!(
+            err("tokens/{name", &PLACEHOLDERS_REG),
+            "invalid token path template 'tokens/{name': unmatched '{'"
+        );
+        assert_eq!(
+            err("tokens/name}", &PLACEHOLDERS_REG),
+            "invalid token path template 'tokens/name}': unmatched '}'"
+        );
+        assert_eq!(
+            err("../{name}.token", &PLACEHOLDERS_REG),
+            "invalid token path template '../{name}.token': \
+            expands to '../bananas.token' containing '..'"
+        );
+        assert_eq!(
+            err("/{name}.token", &PLACEHOLDERS_REG),
+            "invalid token path template '/{name}.token': \
+            expands to absolute path '/bananas.token'"
+        );
+
+        // Placeholder values cannot add or escape path components.
+        let v = Placeholders {
+            name: Some(".."),
+            ..PLACEHOLDERS_REG
+        };
+        assert_eq!(
+            err("tokens/{name}/x", &v),
+            "invalid token path template 'tokens/{name}/x': \
+            expands to 'tokens/../x' containing '..'"
+        );
+
+        let v = Placeholders {
+            name: Some("a/b"),
+            ..PLACEHOLDERS_REG
+        };
+        assert_eq!(
+            err("tokens/{name}", &v),
+            "invalid token path template 'tokens/{name}': \
+            value 'a/b' of placeholder '{name}' contains '/'"
+        );
+
+        let v = Placeholders {
+            index_url: "bananas",
+            ..PLACEHOLDERS_REG
+        };
+        assert_eq!(
+            err("tokens/{host}", &v),
+            "invalid token path template 'tokens/{host}': index URL 'bananas' has no host"
+        );
+    }
+
     #[should_panic(expected = "dir.is_relative()")]
     #[test]
     fn test_path_under_absolute_dir() {
         let p = PassPathBuilder::default()
             .with_name("bananas")
             .under_dir(Path::new("/root"))
-            .build("sparse+https://cargo.itsallbroken.com/index/");
+            .build("sparse+https://cargo.itsallbroken.com/index/")
+            .unwrap();
 
         assert_eq!(p.to_string(), "platanos/are/good/bananas.token");
     }
+
+    #[test]
+    fn test_new_invalid() {
+        assert!(matches!(
+            PassPath::new(PathBuf::from("/root/bananas")),
+            Err(Error::InvalidPath(_))
+        ));
+        assert!(matches!(
+            PassPath::new(PathBuf::from(OsString::from_vec(b"ban\xffanas".to_vec()))),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    proptest! {
+        /// A [`PassPath`] is constructed from exactly the relative, UTF-8
+        /// paths, and displays the path unchanged.
+        #[test]
+        fn prop_new_relative_utf8(bytes in any::<Vec<u8>>()) {
+            let path = PathBuf::from(OsString::from_vec(bytes.clone()));
+            let want = String::from_utf8(bytes)
+                .ok()
+                .filter(|_| path.is_relative());
+
+            prop_assert_eq!(PassPath::new(path).ok().map(|v| v.to_string()), want);
+        }
+
+        /// Expanding an arbitrary template never panics, and never escapes
+        /// the password store root.
+        #[test]
+        fn prop_expand_template_within_store(
+            template in any::<String>(),
+            name in any::<Option<String>>(),
+            index_url in any::<String>(),
+            crate_name in any::<Option<String>>(),
+        ) {
+            let v = Placeholders {
+                name: name.as_deref(),
+                index_url: &index_url,
+                operation: None,
+                crate_name: crate_name.as_deref(),
+            };
+
+            if let Ok(path) = expand_template(&template, &v) {
+                let path = Path::new(&path);
+                prop_assert!(path.is_relative());
+                prop_assert!(path
+                    .components()
+                    .all(|v| v != std::path::Component::ParentDir));
+            }
+        }
+
+        /// A named registry's per-operation token is stored as
+        /// `<dir>/<name>.<operation>.token`.
+        #[test]
+        fn prop_build_named(
+            dir in proptest::option::of("[a-z]{1,8}(/[a-z]{1,8}){0,2}"),
+            name in "[a-zA-Z0-9._-]{1,16}",
+            index_url in any::<String>(),
+            op in proptest::sample::select(OPERATIONS.to_vec()),
+        ) {
+            let mut p = PassPathBuilder::default().with_name(&name).for_operation(op);
+            if let Some(dir) = &dir {
+                p = p.under_dir(Path::new(dir));
+            }
+
+            let dir = dir.as_deref().unwrap_or("cargo-registry");
+            prop_assert_eq!(
+                p.build(&index_url).expect("valid path").to_string(),
+                format!("{dir}/{name}.{op}.token")
+            );
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::IsTerminal,
+    os::fd::{AsFd, BorrowedFd},
+    process::{Command, Stdio},
+};
+
+use cargo_credential::Secret;
+
+use super::Error;
+
+/// The controlling terminal of the process.
+const TTY_DEVICE: &str = "/dev/tty";
+
+/// Open the controlling terminal for reading and writing, returning [`None`]
+/// if the process has no controlling terminal (such as in CI).
+///
+/// Cargo uses the stdin and stdout of a credential provider for the JSON
+/// protocol, so interactive commands must be connected to the terminal
+/// directly.
+pub(crate) fn open() -> Option<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(TTY_DEVICE)
+        .ok()
+}
+
+/// Return the value to set `GPG_TTY` to so that `pinentry` can prompt for a
+/// passphrase on the controlling terminal.
+///
+/// An existing `GPG_TTY` value is always preserved. Otherwise the device name
+/// of the terminal stderr is attached to is resolved (as `$(tty)` would),
+/// returning [`None`] if there is no terminal.
+///
+/// Cargo replaces the stdin and stdout of a credential provider (with a pipe,
+/// or the generic [`TTY_DEVICE`] alias while it runs), so only stderr is still
+/// connected to the user's terminal device.
+pub(crate) fn gpg_tty() -> Option<OsString> {
+    resolve_gpg_tty(std::env::var_os("GPG_TTY"), std::io::stderr().as_fd())
+}
+
+/// Resolve `GPG_TTY` from the `inherited` value, or the terminal device `fd`
+/// is attached to.
+fn resolve_gpg_tty(inherited: Option<OsString>, fd: BorrowedFd<'_>) -> Option<OsString> {
+    if inherited.is_some() {
+        return inherited;
+    }
+
+    if !fd.is_terminal() {
+        return None;
+    }
+
+    let output = Command::new("tty")
+        .stdin(Stdio::from(fd.try_clone_to_owned().ok()?))
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    device_name(&String::from_utf8(output.stdout).ok()?)
+}
+
+/// Parse the terminal device name printed by `tty`.
+///
+/// Returns [`None`] for the generic [`TTY_DEVICE`] alias, which `pinentry`
+/// (run by `gpg-agent` in another process) cannot use to reach the terminal.
+fn device_name(output: &str) -> Option<OsString> {
+    let name = output.trim();
+    (name.starts_with('/') && name != TTY_DEVICE).then(|| OsString::from(name))
+}
+
+/// Print `prompt` to the controlling terminal and read a line of input
+/// without echoing it.
+pub(crate) fn prompt_secret(prompt: &str) -> Result<Secret<String>, Error> {
+    if open().is_none() {
+        return Err(Error::NoTerminal(
+            "to prompt for the token - provide the token with 'cargo login <token>' instead",
+        ));
+    }
+
+    rpassword::prompt_password(prompt)
+        .map(Secret::from)
+        .map_err(Error::Prompt)
+}
+
+/// Returns true if `stderr` from `gpg` indicates `pinentry` could not prompt
+/// for a passphrase.
+pub(crate) fn is_pinentry_failure(stderr: &str) -> bool {
+    ["Inappropriate ioctl for device", "No pinentry"]
+        .iter()
+        .any(|v| stderr.contains(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_name() {
+        assert_eq!(
+            device_name("/dev/pts/0\n"),
+            Some(OsString::from("/dev/pts/0"))
+        );
+        assert_eq!(
+            device_name("/dev/ttys003\n"),
+            Some(OsString::from("/dev/ttys003"))
+        );
+        assert_eq!(device_name("/dev/tty\n"), None);
+        assert_eq!(device_name("not a tty\n"), None);
+    }
+
+    #[test]
+    fn test_resolve_gpg_tty() {
+        let file = tempfile::tempfile().unwrap();
+
+        // An inherited value is always preserved.
+        assert_eq!(
+            resolve_gpg_tty(Some(OsString::from("/dev/pts/7")), file.as_fd()),
+            Some(OsString::from("/dev/pts/7"))
+        );
+
+        // A descriptor that is not a terminal has no device to resolve.
+        assert_eq!(resolve_gpg_tty(None, file.as_fd()), None);
+    }
+
+    #[test]
+    fn test_is_pinentry_failure() {
+        assert!(is_pinentry_failure(
+            "gpg: public key decryption failed: Inappropriate ioctl for device\n\
+            gpg: decryption failed: No secret key"
+        ));
+        assert!(is_pinentry_failure(
+            "gpg: public key decryption failed: No pinentry"
+        ));
+        assert!(!is_pinentry_failure(
+            "gpg: decryption failed: No secret key"
+        ));
+    }
+}
@@ -0,0 +1,10 @@
+//! Well-known registry identifiers.
+
+/// The registry name cargo uses for crates.io.
+pub(crate) const CRATES_IO: &str = "crates-io";
+
+/// The index URL of crates.io.
+pub(crate) const CRATES_IO_INDEX: &str = "sparse+https://index.crates.io/";
+
+/// The git index URL cargo reports for crates.io.
+pub(crate) const CRATES_IO_GIT_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
@@ -0,0 +1,210 @@
+//! Registry token validation.
+//!
+//! Tokens are checked before they are stored on login, so a mis-pasted token
+//! is reported immediately rather than at the next `cargo publish`.
+
+use cargo_credential::RegistryInfo;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::registry::{CRATES_IO_GIT_INDEX, CRATES_IO_INDEX};
+
+/// The prefix of all crates.io API tokens.
+const CRATES_IO_PREFIX: &str = "cio";
+
+/// Failures validating a registry token.
+///
+/// The token itself is never included in the error message.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    /// The token is empty, or only whitespace.
+    #[error("token is empty")]
+    Empty,
+
+    /// The token contains whitespace after copy-paste artifacts are removed.
+    #[error("token contains whitespace - check only the token was pasted")]
+    Whitespace,
+
+    /// A crates.io token does not have the crates.io prefix.
+    #[error(
+        "crates.io tokens start with '{CRATES_IO_PREFIX}' - \
+        check the whole token was pasted (see https://crates.io/settings/tokens)"
+    )]
+    CratesIoPrefix,
+
+    /// The token does not match the configured `--token-pattern`.
+    #[error("token does not match the configured token pattern '{0}'")]
+    Pattern(String),
+}
+
+/// A `--token-pattern` regex that must match the whole token.
+#[derive(Debug)]
+pub(crate) struct Pattern {
+    /// The pattern as configured.
+    source: String,
+    re: Regex,
+}
+
+impl Pattern {
+    /// Compile the regex `v`.
+    pub(crate) fn new(v: &str) -> Result<Self, String> {
+        let re = Regex::new(&format!("^(?:{v})$"))
+            .map_err(|e| format!("invalid token pattern '{v}': {e}"))?;
+
+        Ok(Self {
+            source: v.to_string(),
+            re,
+        })
+    }
+}
+
+/// Remove copy-paste artifacts from `token` and validate the result, returning
+/// the token to store.
+///
+/// Surrounding whitespace and quotes, and a `Bearer ` prefix, are removed.
+///
+/// If a `pattern` is configured the token must match it, otherwise tokens for
+/// crates.io must have the crates.io `cio` prefix.
+pub(crate) fn validate<'a>(
+    token: &'a str,
+    registry: &RegistryInfo<'_>,
+    pattern: Option<&Pattern>,
+) -> Result<&'a str, Error> {
+    let token = normalise(token);
+
+    if token.is_empty() {
+        return Err(Error::Empty);
+    }
+
+    if token.contains(char::is_whitespace) {
+        return Err(Error::Whitespace);
+    }
+
+    match pattern {
+        Some(p) if !p.re.is_match(token) => Err(Error::Pattern(p.source.clone())),
+        Some(_) => Ok(token),
+        None if is_crates_io(registry) && !token.starts_with(CRATES_IO_PREFIX) => {
+            Err(Error::CratesIoPrefix)
+        }
+        None => Ok(token),
+    }
+}
+
+/// Strip surrounding whitespace, matching quotes and an `Authorization` header
+/// `Bearer ` scheme from `token`.
+fn normalise(token: &str) -> &str {
+    let mut token = token.trim();
+
+    for quote in ['"', '\'', '`'] {
+        if let Some(v) = token
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            token = v.trim();
+        }
+    }
+
+    match token.split_once(char::is_whitespace) {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("bearer") => rest.trim(),
+        _ => token,
+    }
+}
+
+/// Returns true if `registry` is crates.io.
+fn is_crates_io(registry: &RegistryInfo<'_>) -> bool {
+    let url = registry.index_url.trim_end_matches('/');
+    url == CRATES_IO_GIT_INDEX || url == CRATES_IO_INDEX.trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(index_url: &str) -> RegistryInfo<'_> {
+        RegistryInfo {
+            index_url,
+            name: None,
+            headers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_normalise() {
+        assert_eq!(normalise("platanos"), "platanos");
+        assert_eq!(normalise("  platanos\n"), "platanos");
+        assert_eq!(normalise("\"platanos\""), "platanos");
+        assert_eq!(normalise("'platanos'"), "platanos");
+        assert_eq!(normalise("`platanos`\r\n"), "platanos");
+        assert_eq!(normalise("Bearer platanos"), "platanos");
+        assert_eq!(normalise("bearer  platanos "), "platanos");
+        assert_eq!(normalise("\"Bearer platanos\""), "platanos");
+        assert_eq!(normalise("Bearerplatanos"), "Bearerplatanos");
+        assert_eq!(normalise("\"platanos"), "\"platanos");
+    }
+
+    #[test]
+    fn test_validate() {
+        let r = registry("sparse+https://itsallbroken.com/index/");
+
+        assert_eq!(validate(" platanos\n", &r, None).unwrap(), "platanos");
+        assert!(matches!(validate("", &r, None), Err(Error::Empty)));
+        assert!(matches!(validate(" \n\t", &r, None), Err(Error::Empty)));
+        assert!(matches!(validate("\"\"", &r, None), Err(Error::Empty)));
+        assert!(matches!(
+            validate("plat anos", &r, None),
+            Err(Error::Whitespace)
+        ));
+    }
+
+    #[test]
+    fn test_validate_crates_io() {
+        for url in [
+            "https://github.com/rust-lang/crates.io-index",
+            "sparse+https://index.crates.io/",
+        ] {
+            let r = registry(url);
+            assert_eq!(validate("cio1234", &r, None).unwrap(), "cio1234");
+            assert_eq!(validate("Bearer cio1234", &r, None).unwrap(), "cio1234");
+
+            let got = validate("platanos", &r, None).expect_err("missing prefix");
+            assert_eq!(
+                got.to_string(),
+                "crates.io tokens start with 'cio' - check the whole token was pasted \
+                (see https://crates.io/settings/tokens)"
+            );
+        }
+
+        // Other registries do not require the prefix.
+        let r = registry("sparse+https://itsallbroken.com/index/");
+        assert_eq!(validate("platanos", &r, None).unwrap(), "platanos");
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        let re = Pattern::new("tok_[a-z]+").expect("valid pattern");
+        let r = registry("sparse+https://itsallbroken.com/index/");
+
+        assert_eq!(
+            validate("tok_bananas", &r, Some(&re)).unwrap(),
+            "tok_bananas"
+        );
+
+        // The pattern must match the whole token.
+        let got = validate("tok_bananas1", &r, Some(&re)).expect_err("no match");
+        assert_eq!(
+            got.to_string(),
+            "token does not match the configured token pattern 'tok_[a-z]+'"
+        );
+
+        // A configured pattern replaces the crates.io prefix check.
+        let re = Pattern::new(".+").expect("valid pattern");
+        let r = registry("sparse+https://index.crates.io/");
+        assert_eq!(validate("platanos", &r, Some(&re)).unwrap(), "platanos");
+    }
+
+    #[test]
+    fn test_pattern_invalid() {
+        let got = Pattern::new("tok_[").expect_err("invalid pattern");
+        assert!(got.starts_with("invalid token pattern 'tok_[': "), "{got}");
+    }
+}
@@ -0,0 +1,208 @@
+//! Verification of a new token against the registry API.
+//!
+//! The registry API URL is discovered from the `config.json` of the sparse
+//! index, and the token used to make an authenticated request for the current
+//! user - a token the registry rejects is never stored.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use ureq::{http::StatusCode, Agent};
+
+use crate::registry::{CRATES_IO_GIT_INDEX, CRATES_IO_INDEX};
+
+/// The authenticated endpoint of a crates.io compatible registry API used to
+/// check a token.
+const ME_ENDPOINT: &str = "api/v1/me";
+
+/// Failures verifying a token with the registry.
+///
+/// The token itself is never included in the error message.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    /// The registry was referred to by name alone, so its index URL (and API)
+    /// is unknown.
+    #[error(
+        "cannot verify token: the registry index URL is unknown - \
+        specify the registry by index URL, or drop --verify"
+    )]
+    UnknownIndex,
+
+    /// The index is not a sparse (HTTP) index, so the API URL cannot be
+    /// discovered.
+    #[error(
+        "cannot verify token for git index '{0}' - \
+        only sparse (sparse+https://) registry indexes are supported"
+    )]
+    UnsupportedIndex(String),
+
+    /// An HTTP request failed.
+    #[error("error requesting {url}: {source}")]
+    Request {
+        url: String,
+        #[source]
+        source: ureq::Error,
+    },
+
+    /// The registry responded with an unexpected status code.
+    #[error("unexpected response from {url}: HTTP {status}")]
+    Status { url: String, status: StatusCode },
+
+    /// The index `config.json` does not contain an API URL.
+    #[error("registry config {0} has no 'api' URL")]
+    NoApi(String),
+
+    /// The registry rejected the token.
+    #[error("token rejected by {url} (HTTP {status}) - the existing token has not been changed")]
+    Rejected { url: String, status: StatusCode },
+}
+
+/// Verify `token` with the API of the registry at `index_url`, failing if the
+/// registry rejects it.
+///
+/// Requests are aborted if they do not complete within `timeout`.
+pub(crate) fn verify(index_url: &str, token: &str, timeout: Option<Duration>) -> Result<(), Error> {
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(timeout)
+        .http_status_as_error(false)
+        .build()
+        .new_agent();
+
+    let config_url = config_url(index_url)?;
+
+    // Registries that require authentication for all requests also require it
+    // for the index config.
+    let mut res = get(&agent, &config_url, None)?;
+    if res.status() == StatusCode::UNAUTHORIZED {
+        res = get(&agent, &config_url, Some(token))?;
+    }
+
+    let config = match res.status() {
+        v if v.is_success() => read_body(&config_url, &mut res)?,
+        v if is_rejected(v) => {
+            return Err(Error::Rejected {
+                url: config_url,
+                status: v,
+            })
+        }
+        status => {
+            return Err(Error::Status {
+                url: config_url,
+                status,
+            })
+        }
+    };
+
+    let api = serde_json::from_str::<serde_json::Value>(&config)
+        .ok()
+        .and_then(|v| v.get("api")?.as_str().map(ToString::to_string))
+        .ok_or_else(|| Error::NoApi(config_url.clone()))?;
+
+    let me_url = format!("{}/{ME_ENDPOINT}", api.trim_end_matches('/'));
+    let res = get(&agent, &me_url, Some(token))?;
+
+    match res.status() {
+        v if v.is_success() => Ok(()),
+        v if is_rejected(v) => Err(Error::Rejected {
+            url: me_url,
+            status: v,
+        }),
+        status => Err(Error::Status {
+            url: me_url,
+            status,
+        }),
+    }
+}
+
+/// Return the URL of the `config.json` of the sparse index at `index_url`.
+fn config_url(index_url: &str) -> Result<String, Error> {
+    if index_url.is_empty() {
+        return Err(Error::UnknownIndex);
+    }
+
+    let index_url = match index_url.trim_end_matches('/') {
+        v if v == CRATES_IO_GIT_INDEX => CRATES_IO_INDEX,
+        _ => index_url,
+    };
+
+    let url = index_url
+        .strip_prefix("sparse+")
+        .ok_or_else(|| Error::UnsupportedIndex(index_url.to_string()))?;
+
+    Ok(format!("{}/config.json", url.trim_end_matches('/')))
+}
+
+/// Send a GET request for `url`, optionally authenticated with `token`.
+fn get(
+    agent: &Agent,
+    url: &str,
+    token: Option<&str>,
+) -> Result<ureq::http::Response<ureq::Body>, Error> {
+    let mut req = agent.get(url);
+    if let Some(token) = token {
+        req = req.header("Authorization", token);
+    }
+
+    req.call().map_err(|source| Error::Request {
+        url: url.to_string(),
+        source,
+    })
+}
+
+/// Read the response body of the request for `url`.
+fn read_body(url: &str, res: &mut ureq::http::Response<ureq::Body>) -> Result<String, Error> {
+    res.body_mut()
+        .read_to_string()
+        .map_err(|source| Error::Request {
+            url: url.to_string(),
+            source,
+        })
+}
+
+/// Returns true if `status` indicates the token was not accepted.
+fn is_rejected(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_url() {
+        assert_eq!(
+            config_url("sparse+https://itsallbroken.com/index/").unwrap(),
+            "https://itsallbroken.com/index/config.json"
+        );
+        assert_eq!(
+            config_url("sparse+http://127.0.0.1:4242/index").unwrap(),
+            "http://127.0.0.1:4242/index/config.json"
+        );
+
+        // The git index of crates.io is mapped to the sparse index.
+        assert_eq!(
+            config_url("https://github.com/rust-lang/crates.io-index").unwrap(),
+            "https://index.crates.io/config.json"
+        );
+    }
+
+    #[test]
+    fn test_config_url_git_index() {
+        let got = config_url("https://itsallbroken.com/index.git").expect_err("git index");
+        assert_eq!(
+            got.to_string(),
+            "cannot verify token for git index 'https://itsallbroken.com/index.git' - \
+            only sparse (sparse+https://) registry indexes are supported"
+        );
+    }
+
+    #[test]
+    fn test_config_url_unknown_index() {
+        let got = config_url("").expect_err("no index URL");
+        assert_eq!(
+            got.to_string(),
+            "cannot verify token: the registry index URL is unknown - \
+            specify the registry by index URL, or drop --verify"
+        );
+    }
+}
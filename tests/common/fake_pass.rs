@@ -0,0 +1,41 @@
+use std::{ffi::OsString, os::unix::fs::PermissionsExt};
+
+use tempfile::{tempdir, TempDir};
+
+/// A stand-in `pass` executable that runs a shell script, used to simulate a
+/// misbehaving `pass` installation.
+///
+/// When dropped the executable is deleted.
+#[derive(Debug)]
+pub(crate) struct FakePass {
+    dir: TempDir,
+}
+
+impl FakePass {
+    /// Construct a fake `pass` that executes the shell `script`.
+    pub(crate) fn new(script: &str) -> Self {
+        Self::with_bin("pass", script)
+    }
+
+    /// Construct a fake executable named `name` (such as another backend
+    /// CLI) that executes the shell `script`.
+    pub(crate) fn with_bin(name: &str, script: &str) -> Self {
+        let dir = tempdir().expect("failed to create tempdir");
+        let bin = dir.path().join(name);
+
+        std::fs::write(&bin, format!("#!/bin/sh\n{script}\n")).expect("failed to write fake pass");
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755))
+            .expect("failed to make fake pass executable");
+
+        Self { dir }
+    }
+
+    /// Return a `PATH` value that resolves the executable to this fake.
+    pub(crate) fn path_env(&self) -> OsString {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        std::env::join_paths(
+            std::iter::once(self.dir.path().to_path_buf()).chain(std::env::split_paths(&path)),
+        )
+        .expect("invalid PATH")
+    }
+}
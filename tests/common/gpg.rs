@@ -6,6 +6,17 @@ use std::{
 
 use tempfile::{tempdir, TempDir};
 
+/// The `gpg --gen-key --batch` parameters of the test key.
+const KEY_PARAMS: &str = "Key-Type: eddsa
+Key-Curve: Ed25519
+Key-Usage: sign
+Subkey-Type: ecdh
+Subkey-Curve: Curve25519
+Subkey-Usage: encrypt
+Name-Real: Cargo Test Key
+Name-Email: bananas@itsallbroken.com
+Expire-Date: 0";
+
 /// A handle to a temporary, isolated GPG keychain containing a single GPG key
 /// for `bananas@itsallbroken.com`.
 ///
@@ -28,12 +39,31 @@ impl GpgHandle {
     pub(crate) fn home_dir(&self) -> &Path {
         self.dir.path()
     }
-}
 
-impl Default for GpgHandle {
-    fn default() -> Self {
+    /// Construct a keychain whose key is protected by `passphrase`, with a
+    /// `gpg-agent` that has no usable `pinentry` to prompt for it.
+    pub(crate) fn with_passphrase(passphrase: &str) -> Self {
         let dir = tempdir().expect("cannot create temp directory");
+        let gpg = Self::generate(dir, Some(passphrase));
+
+        std::fs::write(
+            gpg.home_dir().join("gpg-agent.conf"),
+            "pinentry-program /nonexistent/pinentry\nallow-loopback-pinentry\n",
+        )
+        .expect("failed to write gpg-agent.conf");
+
+        let status = Command::new("gpgconf")
+            .env("GNUPGHOME", gpg.home_dir())
+            .args(["--kill", "gpg-agent"])
+            .status()
+            .expect("failed to spawn gpgconf");
+        assert!(status.success(), "restarting gpg-agent failed");
+
+        gpg
+    }
 
+    /// Generate the test key in `dir`, protected by `passphrase` if any.
+    fn generate(dir: TempDir, passphrase: Option<&str>) -> Self {
         let mut child = Command::new("gpg")
             .env("GNUPGHOME", dir.path())
             .arg("--gen-key")
@@ -44,22 +74,13 @@ impl Default for GpgHandle {
             .spawn()
             .expect("failed to spawn pass init command");
 
+        let params = match passphrase {
+            Some(v) => format!("{KEY_PARAMS}\nPassphrase: {v}"),
+            None => format!("%no-protection\n{KEY_PARAMS}"),
+        };
+
         let mut stdin = child.stdin.take().expect("no stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(
-                "%no-protection
-Key-Type: eddsa
-Key-Curve: Ed25519
-Key-Usage: sign
-Subkey-Type: ecdh
-Subkey-Curve: Curve25519
-Subkey-Usage: encrypt
-Name-Real: Cargo Test Key
-Name-Email: bananas@itsallbroken.com
-Expire-Date: 0"
-                    .as_bytes(),
-            )
-        });
+        std::thread::spawn(move || stdin.write_all(params.as_bytes()));
 
         let out = child.wait_with_output().expect("pass init exec failure");
 
@@ -73,3 +94,10 @@ Expire-Date: 0"
         Self { dir }
     }
 }
+
+impl Default for GpgHandle {
+    fn default() -> Self {
+        let dir = tempdir().expect("cannot create temp directory");
+        Self::generate(dir, None)
+    }
+}
@@ -1,2 +1,5 @@
+pub mod fake_pass;
 pub mod gpg;
 pub mod pass;
+#[cfg(feature = "verify")]
+pub mod registry;
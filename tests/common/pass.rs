@@ -1,4 +1,5 @@
 use std::{
+    io::Write,
     path::Path,
     process::{Command, Stdio},
 };
@@ -40,4 +41,48 @@ impl PassHandle {
     pub(crate) fn dir(&self) -> &Path {
         self.dir.path()
     }
+
+    /// Write the multi-line `content` to `path` in the password store,
+    /// overwriting any existing entry.
+    pub(crate) fn insert(&self, gpg: &GpgHandle, path: &str, content: &str) {
+        let mut child = gpg
+            .set_scope(
+                Command::new("pass")
+                    .env("PASSWORD_STORE_DIR", self.dir())
+                    .arg("insert")
+                    .arg("--multiline")
+                    .arg("--force")
+                    .arg(path)
+                    .stdout(Stdio::null())
+                    .stdin(Stdio::piped()),
+            )
+            .spawn()
+            .expect("failed to spawn pass insert");
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+
+        assert!(child.wait().unwrap().success(), "pass insert failed");
+    }
+
+    /// Read the full content of the entry at `path` in the password store.
+    pub(crate) fn show(&self, gpg: &GpgHandle, path: &str) -> String {
+        let out = gpg
+            .set_scope(
+                Command::new("pass")
+                    .env("PASSWORD_STORE_DIR", self.dir())
+                    .arg("show")
+                    .arg(path),
+            )
+            .output()
+            .expect("failed to spawn pass show");
+
+        assert!(out.status.success(), "pass show failed");
+
+        String::from_utf8(out.stdout).unwrap()
+    }
 }
@@ -0,0 +1,79 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+};
+
+/// A stand-in HTTP registry serving a sparse index `config.json` and the
+/// authenticated `/api/v1/me` endpoint, which accepts only `token`.
+///
+/// Requests are served on a background thread until the test process exits.
+#[derive(Debug)]
+pub(crate) struct FakeRegistry {
+    addr: String,
+}
+
+impl FakeRegistry {
+    /// Start a registry that accepts `token`.
+    pub(crate) fn new(token: &'static str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let addr = listener.local_addr().expect("no local addr").to_string();
+
+        let api = format!("http://{addr}/");
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                serve(stream, &api, token);
+            }
+        });
+
+        Self { addr }
+    }
+
+    /// Return the sparse index URL of this registry.
+    pub(crate) fn index_url(&self) -> String {
+        format!("sparse+http://{}/index/", self.addr)
+    }
+}
+
+/// Respond to a single HTTP request read from `stream`.
+fn serve(mut stream: TcpStream, api: &str, token: &str) {
+    let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let path = request_line
+        .split(' ')
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    // Read the headers, capturing the Authorization value.
+    let mut auth = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            if k.eq_ignore_ascii_case("authorization") {
+                auth = Some(v.trim().to_string());
+            }
+        }
+    }
+
+    let (status, body) = match path.as_str() {
+        "/index/config.json" => ("200 OK", format!(r#"{{"dl":"{api}dl","api":"{api}"}}"#)),
+        "/api/v1/me" if auth.as_deref() == Some(token) => {
+            ("200 OK", r#"{"user":{"login":"bananas"}}"#.to_string())
+        }
+        "/api/v1/me" => ("403 Forbidden", r#"{"errors":[]}"#.to_string()),
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
@@ -1,7 +1,9 @@
 mod common;
 
-use assert_cmd::Command;
-use common::{gpg::GpgHandle, pass::PassHandle};
+use assert_cmd::{assert::Assert, Command};
+#[cfg(feature = "verify")]
+use common::registry::FakeRegistry;
+use common::{fake_pass::FakePass, gpg::GpgHandle, pass::PassHandle};
 use predicates::prelude::{predicate, PredicateBooleanExt};
 
 #[test]
@@ -86,7 +88,7 @@ fn test_token_lifecycle_with_name() {
         &gpg,
     );
 
-    let read_err_response = "{\"Err\":{\"kind\":\"other\",\"message\":\"pass exited with a non-zero status code (stdout=\'\', stderr=\'Error: cargo-registry/crates-io.token is not in the password store.\')\",\"caused-by\":[]}}";
+    let read_err_response = r#"{"Err":{"kind":"not-found"}}"#;
 
     run_plugin(
         format!("{read_request}\n"),
@@ -142,7 +144,7 @@ fn test_token_lifecycle_without_name() {
         &gpg,
     );
 
-    let read_err_response = "{\"Err\":{\"kind\":\"other\",\"message\":\"pass exited with a non-zero status code (stdout=\'\', stderr=\'Error: cargo-registry/https___itsallbroken_com_rust_lang_crates_io_index.token is not in the password store.\')\",\"caused-by\":[]}}";
+    let read_err_response = r#"{"Err":{"kind":"not-found"}}"#;
 
     run_plugin(
         format!("{read_request}\n"),
@@ -152,6 +154,278 @@ fn test_token_lifecycle_without_name() {
     );
 }
 
+/// Reading or deleting a token that does not exist in the password store
+/// returns a "not found" error, allowing cargo to fall through to the next
+/// credential provider.
+#[test]
+fn test_missing_token_not_found() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let hello = r#"{"v":[1]}"#;
+    let not_found_response = r#"{"Err":{"kind":"not-found"}}"#;
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": []}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{not_found_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let logout_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind":"logout","args":[]}"#;
+    run_plugin(
+        format!("{logout_request}\n"),
+        &format!("{hello}\n{not_found_response}\n"),
+        &pass,
+        &gpg,
+    );
+}
+
+/// Entries following the `pass` multi-line convention return only the first
+/// line as the token, and logging in again preserves the metadata lines.
+#[test]
+fn test_multiline_entry() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    pass.insert(
+        &gpg,
+        "cargo-registry/crates-io.token",
+        "platanos\nurl: https://itsallbroken.com/tokens\nscopes: publish-new\n",
+    );
+
+    let hello = r#"{"v":[1]}"#;
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": []}"#;
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{token_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "bananas","args": []}"#;
+    let login_response = r#"{"Ok":{"kind":"login"}}"#;
+
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/crates-io.token"),
+        "bananas\nurl: https://itsallbroken.com/tokens\nscopes: publish-new\n"
+    );
+}
+
+/// Tokens with an `expires` metadata line are cached until their expiry time,
+/// and are never returned once expired.
+#[test]
+fn test_token_expiry() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let hello = r#"{"v":[1]}"#;
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": []}"#;
+
+    //
+    // A token that expires in the far future.
+    //
+
+    pass.insert(
+        &gpg,
+        "cargo-registry/crates-io.token",
+        "platanos\nexpires: 2099-01-01T00:00:00Z\n",
+    );
+
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"expires","expiration":4070908800,"operation_independent":true}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{token_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    //
+    // A token close to expiry causes a warning to be printed.
+    //
+
+    let read_request_warn = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": ["--expiry-warning=36500d"]}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{read_request_warn}\n"))
+        .assert()
+        .stdout(predicate::eq(format!("{hello}\n{token_response}\n")))
+        .stderr(predicate::str::contains(
+            "warning: token in cargo-registry/crates-io.token expires in",
+        ));
+
+    //
+    // An expired token is never returned.
+    //
+
+    pass.insert(
+        &gpg,
+        "cargo-registry/crates-io.token",
+        "platanos\nexpires: 1733043600\n",
+    );
+
+    let err_response = r#"{"Err":{"kind":"other","message":"token in cargo-registry/crates-io.token expired at 2024-12-01T09:00:00Z, use 'cargo login' to store a new token","caused-by":[]}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{err_response}\n"),
+        &pass,
+        &gpg,
+    );
+    //
+    // Logging in with a new token replaces the expired token, and its expiry.
+    //
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "bananas","args": []}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#),
+        &pass,
+        &gpg,
+    );
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/crates-io.token"),
+        "bananas\n"
+    );
+
+    let token_response =
+        r#"{"Ok":{"kind":"get","token":"bananas","cache":"session","operation_independent":true}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{token_response}\n"),
+        &pass,
+        &gpg,
+    );
+}
+
+/// Operation-specific tokens are used in preference to the generic registry
+/// token, and cargo is told to re-request tokens per operation while they
+/// exist.
+#[test]
+fn test_operation_specific_token() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let hello = r#"{"v":[1]}"#;
+    let login_response = r#"{"Ok":{"kind":"login"}}"#;
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "platanos","args": []}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "publish-platanos","args": ["--operation=publish"]}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    assert!(pass
+        .dir()
+        .join("cargo-registry/crates-io.publish.token.gpg")
+        .exists());
+
+    //
+    // Reads and yanks use the generic token, but it is not operation
+    // independent.
+    //
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": []}"#;
+    let generic_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":false}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{generic_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let yank_request = r#"{"v":1,"kind":"get","operation":"yank","name":"bananas","vers":"0.1.0","registry":{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"args": []}"#;
+    run_plugin(
+        format!("{yank_request}\n"),
+        &format!("{hello}\n{generic_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    //
+    // Publish uses the publish token.
+    //
+
+    let publish_request = r#"{"v":1,"kind":"get","operation":"publish","name":"bananas","vers":"0.1.0","cksum":"...","registry":{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"args": []}"#;
+    let publish_response = r#"{"Ok":{"kind":"get","token":"publish-platanos","cache":"session","operation_independent":false}}"#;
+    run_plugin(
+        format!("{publish_request}\n"),
+        &format!("{hello}\n{publish_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    //
+    // Once the publish token is removed, the generic token is used for all
+    // operations.
+    //
+
+    let logout_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind":"logout","args":["--operation=publish"]}"#;
+    run_plugin(
+        format!("{logout_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"logout"}}"#),
+        &pass,
+        &gpg,
+    );
+
+    let generic_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    run_plugin(
+        format!("{publish_request}\n"),
+        &format!("{hello}\n{generic_response}\n"),
+        &pass,
+        &gpg,
+    );
+}
+
+/// Logging in with `--key-subject` generates and stores an asymmetric secret
+/// key, which is used to sign a PASETO token for each request.
+#[test]
+fn test_paseto_login() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","args": ["--key-subject=platanos"]}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(r#"{"Ok":{"kind":"login"}}"#))
+        .stderr(predicate::str::contains("k3.public."));
+
+    let entry = pass.show(&gpg, "cargo-registry/crates-io.token");
+    assert!(entry.starts_with("k3.secret."));
+    assert!(entry.ends_with("\nsubject: platanos\n"));
+
+    let publish_request = r#"{"v":1,"kind":"get","operation":"publish","name":"bananas","vers":"0.1.0","cksum":"...","registry":{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"args": []}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{publish_request}\n"))
+        .assert()
+        .stdout(
+            predicate::str::contains(r#"{"Ok":{"kind":"get","token":"v3.public."#)
+                .and(predicate::str::contains(
+                    r#""cache":"never","operation_independent":false}}"#,
+                ))
+                .and(predicate::str::contains("k3.secret.").not()),
+        );
+}
+
 /// Ensure passing a custom path in the Cargo credential config is handled
 /// correctly.
 ///
@@ -192,6 +466,141 @@ fn test_custom_path() {
     assert!(pass.dir().join("custom/path/bananas.secret.gpg").exists());
 }
 
+/// When configured to decrypt tokens with `gpg` directly, tokens are read
+/// without executing `pass`.
+#[test]
+fn test_direct_gpg_read() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let hello = r#"{"v":[1]}"#;
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "platanos","args": []}"#;
+    let login_response = r#"{"Ok":{"kind":"login"}}"#;
+
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    // Construct a PATH that contains gpg, but not pass.
+    let bin = tempfile::tempdir().expect("failed to create tempdir");
+    let gpg_bin = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+        .map(|v| v.join("gpg"))
+        .find(|v| v.is_file())
+        .expect("gpg not found in PATH");
+    std::os::unix::fs::symlink(gpg_bin, bin.path().join("gpg")).unwrap();
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": ["--direct-gpg"]}"#;
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+
+    plugin_cmd(&pass, &gpg)
+        .env("PATH", bin.path())
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(predicate::eq(format!("{hello}\n{token_response}\n")));
+
+    // Missing entries fall back to pass to report the error, which is not
+    // installed.
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"bananas"},"kind": "get","operation": "read","args": ["--direct-gpg"]}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("PATH", bin.path())
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains("error executing pass"));
+}
+
+/// Direct GPG decryption prompts for the key passphrase on the terminal when
+/// gpg-agent has no pinentry to ask for it.
+#[test]
+fn test_direct_gpg_loopback_prompt() {
+    let gpg = GpgHandle::with_passphrase("platanos-passphrase");
+    let pass = PassHandle::new(&gpg);
+    pass.insert(&gpg, "cargo-registry/crates-io.token", "platanos\n");
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": ["--direct-gpg"]}"#;
+    let requests = tempfile::NamedTempFile::new().expect("failed to create tempfile");
+    std::fs::write(requests.path(), format!("{read_request}\n")).unwrap();
+
+    // Run the plugin with a terminal on which the passphrase is typed, reading
+    // the request from a file.
+    let mut cmd = Command::new("script");
+    cmd.arg("-qec")
+        .arg(format!(
+            "{} --cargo-plugin < {}",
+            assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")).display(),
+            requests.path().display()
+        ))
+        .arg("/dev/null");
+
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+
+    isolate(cmd, &pass, &gpg)
+        .write_stdin("platanos-passphrase\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "passphrase for cargo-registry/crates-io.token",
+        ))
+        .stdout(predicate::str::contains(token_response));
+}
+
+/// Logging in replaces an existing entry that cannot be decrypted, rather than
+/// failing.
+#[test]
+fn test_login_replaces_unreadable_entry() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    // An entry encrypted for a key that is no longer available.
+    pass.insert(&gpg, "cargo-registry/work.token", "bananas\nurl: x\n");
+    std::fs::write(
+        pass.dir().join("cargo-registry/work.token.gpg"),
+        "not a gpg message",
+    )
+    .unwrap();
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "login","token": "platanos","args": []}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#))
+        .stderr(predicate::str::contains(
+            "warning: replacing unreadable token in cargo-registry/work.token",
+        ));
+
+    assert_eq!(pass.show(&gpg, "cargo-registry/work.token"), "platanos\n");
+}
+
+/// Entries in a gopass sub-store have no file in the root store, but are still
+/// read through gopass.
+#[test]
+fn test_gopass_sub_store_read() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    // A gopass with the token mounted outside the root store, at the path
+    // derived from the index URL rather than the (first) named path.
+    let fake = FakePass::with_bin(
+        "gopass",
+        r#"case "$1 $3" in
+            "show cargo-registry/itsallbroken.com/"*) echo platanos ;;
+            *) exit 1 ;;
+        esac"#,
+    );
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "get","operation": "read","args": ["backend=gopass"]}"#;
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("PATH", fake.path_env())
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{token_response}\n"));
+}
+
 /// Only relative paths are accepted in the credential config when specifying
 /// the token path to use.
 #[test]
@@ -212,38 +621,956 @@ fn test_request_absolute_token_path() {
     );
 }
 
-/// Exactly 0 or 1 arguments are accepted in the credential config, with more
-/// than 1 causing an error to be returned.
+/// Tokens are validated before being stored on login, with copy-paste
+/// artifacts removed.
 #[test]
-fn test_request_too_many_args() {
+fn test_login_token_validation() {
     let gpg = GpgHandle::default();
     let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
 
-    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "platanos","args": ["1","2"]}"#;
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "   ","args": []}"#;
+    let err_response = r#"{"Err":{"kind":"other","message":"token is empty","caused-by":[]}}"#;
     run_plugin(
         format!("{login_request}\n"),
-        &format!(
-            "{hello}\n{response}\n",
-            hello = r#"{"v":[1]}"#,
-            response = r#"{"Err":{"kind":"other","message":"too many arguments specified in cargo credential provider config","caused-by":[]}}"#,
-        ),
+        &format!("{hello}\n{err_response}\n"),
         &pass,
         &gpg,
     );
-}
 
-fn run_plugin(stdin: String, want_stdout: &str, pass: &PassHandle, gpg: &GpgHandle) {
-    Command::cargo_bin(env!("CARGO_PKG_NAME"))
-        .unwrap()
-        .write_stdin(stdin)
-        .arg("--cargo-plugin")
-        .env_remove("PASSWORD_STORE_SIGNING_KEY")
-        .env_remove("PASSWORD_STORE_DIR")
-        .env_remove("PASSWORD_STORE_GENERATED_LENGTH")
-        .env_remove("GPG_TTY")
-        .env("PASSWORD_STORE_DIR", pass.dir())
-        .env("GNUPGHOME", gpg.home_dir())
-        .assert()
-        .stdout(predicate::eq(want_stdout.as_bytes()))
-        .stderr(predicate::str::contains("bananas").not()); // Never prints the token
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://index.crates.io/","name":"crates-io"},"kind": "login","token": "platanos","args": []}"#;
+    let err_response = r#"{"Err":{"kind":"other","message":"crates.io tokens start with 'cio' - check the whole token was pasted (see https://crates.io/settings/tokens)","caused-by":[]}}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{err_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "bananas","args": ["--token-pattern=tok_.+"]}"#;
+    let err_response = r#"{"Err":{"kind":"other","message":"token does not match the configured token pattern 'tok_.+'","caused-by":[]}}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{err_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "Bearer tok_platanos ","args": ["--token-pattern=tok_.+"]}"#;
+    let login_response = r#"{"Ok":{"kind":"login"}}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/crates-io.token"),
+        "tok_platanos\n"
+    );
+}
+
+/// With `--verify` a token is checked against the registry API on login, and a
+/// rejected token never replaces the stored token.
+#[cfg(feature = "verify")]
+#[test]
+fn test_login_verify() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let registry = FakeRegistry::new("platanos");
+    let hello = r#"{"v":[1]}"#;
+
+    let login_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"{}","name":"bananas"}},"kind": "login","token": "platanos","args": ["--verify"]}}"#,
+        registry.index_url()
+    );
+    let login_response = r#"{"Ok":{"kind":"login"}}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{login_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"{}","name":"bananas"}},"kind": "login","token": "bananas","args": ["--verify"]}}"#,
+        registry.index_url()
+    );
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(
+            "/api/v1/me (HTTP 403 Forbidden) - the existing token has not been changed",
+        ));
+
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/bananas.token"),
+        "platanos\n"
+    );
+
+    // A registry named on the command line has no index URL to verify with.
+    cli_cmd(&pass, &gpg, &["login", "bananas", "--verify"])
+        .write_stdin("bananas\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "cannot verify token: the registry index URL is unknown",
+        ));
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/bananas.token"),
+        "platanos\n"
+    );
+}
+
+/// Per-registry settings are read from the config file, with the provider
+/// args taking precedence.
+#[test]
+fn test_config_file() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let work = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    let config_dir = tempfile::tempdir().unwrap();
+    let config = config_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+            [registries.work]
+            path = "team/work.token"
+            store-dir = "{}"
+            cache = "never"
+
+            [registries.work.operations]
+            publish = "team/release.token"
+            "#,
+            work.dir().display()
+        ),
+    )
+    .unwrap();
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "login","token": "platanos","args": []}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{{\"Ok\":{{\"kind\":\"login\"}}}}\n"));
+
+    // The token is stored in the configured store, at the configured path.
+    assert_eq!(work.show(&gpg, "team/work.token"), "platanos\n");
+    assert!(!pass.dir().join("team/work.token.gpg").exists());
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "get","operation": "read","args": []}"#;
+    let token_response =
+        r#"{"Ok":{"kind":"get","token":"platanos","cache":"never","operation_independent":true}}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{token_response}\n"));
+
+    // Provider args take precedence over the config file.
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "login","token": "publish-platanos","args": ["--operation=publish"]}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .success();
+    assert_eq!(work.show(&gpg, "team/release.token"), "publish-platanos\n");
+
+    cli_cmd(&pass, &gpg, &["show-path", "work", "other/"])
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .assert()
+        .success()
+        .stdout("other/work.token\n");
+
+    // Unknown keys are reported.
+    std::fs::write(&config, "[registries.work]\ndir = \"team/\"\n").unwrap();
+    cli_cmd(&pass, &gpg, &["show-path", "work"])
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "unknown key 'dir' in [registries.work]",
+        ));
+}
+
+/// Options can be specified as `key=value` provider args.
+#[test]
+fn test_key_value_args() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let work = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    let login_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"}},"kind": "login","token": "platanos","args": ["path=team/","store-dir={}","cache=never"]}}"#,
+        work.dir().display()
+    );
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#),
+        &pass,
+        &gpg,
+    );
+    assert_eq!(work.show(&gpg, "team/work.token"), "platanos\n");
+
+    let read_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"}},"kind": "get","operation": "read","args": ["path=team/","store-dir={}","cache=never"]}}"#,
+        work.dir().display()
+    );
+    let token_response =
+        r#"{"Ok":{"kind":"get","token":"platanos","cache":"never","operation_independent":true}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{token_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind": "login","token": "platanos","args": ["team/","path=work/"]}"#;
+    let err_response = r#"{"Err":{"kind":"other","message":"duplicate credential provider option 'path'","caused-by":[]}}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{err_response}\n"),
+        &pass,
+        &gpg,
+    );
+}
+
+/// All index URLs aliased to the same registry share one token.
+#[test]
+fn test_registry_aliases() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    let config_dir = tempfile::tempdir().unwrap();
+    let config = config_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        r#"
+        [aliases.urls]
+        "https://git.itsallbroken.com/index.git" = "sparse+https://itsallbroken.com/index/"
+
+        [aliases.hosts]
+        "staging.itsallbroken.com" = "itsallbroken.com"
+
+        [registries."sparse+https://itsallbroken.com/index/"]
+        path = "work/token"
+        "#,
+    )
+    .unwrap();
+
+    // Login via the git mirror stores the token for the canonical URL.
+    let login_request = r#"{"v":1,"registry":{"index-url":"https://git.itsallbroken.com/index.git"},"kind":"login","token":"platanos","args":[]}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#));
+    assert_eq!(pass.show(&gpg, "work/token"), "platanos\n");
+
+    let read_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    for url in [
+        "sparse+https://itsallbroken.com/index/",
+        "sparse+https://staging.itsallbroken.com/index/",
+    ] {
+        let read_request = format!(
+            r#"{{"v":1,"kind":"get","operation":"read","registry":{{"index-url":"{url}"}},"args":[]}}"#
+        );
+        plugin_cmd(&pass, &gpg)
+            .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+            .write_stdin(format!("{read_request}\n"))
+            .assert()
+            .stdout(format!("{hello}\n{read_response}\n"));
+    }
+
+    cli_cmd(
+        &pass,
+        &gpg,
+        &[
+            "show-path",
+            "sparse+https://staging.itsallbroken.com/index/",
+        ],
+    )
+    .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+    .assert()
+    .success()
+    .stdout("work/token\n");
+}
+
+/// Tokens stored at the (collision-prone) legacy path of an unnamed registry
+/// are still found, while new tokens are stored at the current path.
+#[test]
+fn test_legacy_path_migration() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    pass.insert(
+        &gpg,
+        "cargo-registry/https___itsallbroken_com_index_.token",
+        "legacy-platanos",
+    );
+
+    let read_request = r#"{"v":1,"kind":"get","operation":"read","registry":{"index-url":"sparse+https://itsallbroken.com/index/"},"args":[]}"#;
+    let legacy_response = r#"{"Ok":{"kind":"get","token":"legacy-platanos","cache":"session","operation_independent":true}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{legacy_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    // A new token is stored at the current path, and used in preference.
+    let login_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/index/"},"kind":"login","token":"platanos","args":[]}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#),
+        &pass,
+        &gpg,
+    );
+    assert_eq!(
+        pass.show(
+            &gpg,
+            "cargo-registry/itsallbroken.com/689322e6387231a7.token"
+        ),
+        "platanos\n"
+    );
+
+    let read_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{read_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    // Logout removes both.
+    let logout_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/index/"},"kind":"logout","args":[]}"#;
+    run_plugin(
+        format!("{logout_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"logout"}}"#),
+        &pass,
+        &gpg,
+    );
+
+    let not_found_response = r#"{"Err":{"kind":"not-found"}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{not_found_response}\n"),
+        &pass,
+        &gpg,
+    );
+}
+
+/// A token stored for a registry by URL is found when the registry is referred
+/// to by name, and the debug output reports which candidate path matched.
+#[test]
+fn test_candidate_paths() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    let login_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/index/"},"kind":"login","token":"platanos","args":[]}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#),
+        &pass,
+        &gpg,
+    );
+
+    let read_request = r#"{"v":1,"kind":"get","operation":"read","registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"args":["--debug"]}"#;
+    let read_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{read_response}\n"))
+        .stderr(predicate::str::contains(
+            "debug: no token in cargo-registry/work.token\n\
+            debug: using token in cargo-registry/itsallbroken.com/689322e6387231a7.token\n",
+        ));
+
+    // Login by name stores the canonical (name-based) token, which is then
+    // preferred.
+    let login_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind":"login","token":"named-platanos","args":[]}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#),
+        &pass,
+        &gpg,
+    );
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/work.token"),
+        "named-platanos\n"
+    );
+
+    let read_request = r#"{"v":1,"kind":"get","operation":"read","registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"args":[]}"#;
+    let read_response = r#"{"Ok":{"kind":"get","token":"named-platanos","cache":"session","operation_independent":true}}"#;
+    plugin_cmd(&pass, &gpg)
+        .env("CARGO_CREDENTIAL_PASS_DEBUG", "1")
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{read_response}\n"))
+        .stderr(predicate::str::contains(
+            "debug: using token in cargo-registry/work.token\n",
+        ));
+}
+
+/// Templated token paths address tokens per crate and operation.
+#[test]
+fn test_path_template() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let hello = r#"{"v":[1]}"#;
+
+    pass.insert(&gpg, "tokens/itsallbroken.com/default/default", "platanos");
+    pass.insert(
+        &gpg,
+        "tokens/itsallbroken.com/bananas/publish",
+        "publish-platanos",
+    );
+
+    let publish_request = r#"{"v":1,"kind":"get","operation":"publish","name":"bananas","vers":"0.1.0","cksum":"...","registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"args": ["path=tokens/{host}/{crate}/{operation}"]}"#;
+    let publish_response = r#"{"Ok":{"kind":"get","token":"publish-platanos","cache":"session","operation_independent":false}}"#;
+    run_plugin(
+        format!("{publish_request}\n"),
+        &format!("{hello}\n{publish_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let read_request = r#"{"v":1,"kind":"get","operation":"read","registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"args": ["path=tokens/{host}/{crate}/{operation}"]}"#;
+    // The token read with `{crate}` expanded to "default" must not be reused
+    // for other operations.
+    let read_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":false}}"#;
+    run_plugin(
+        format!("{read_request}\n"),
+        &format!("{hello}\n{read_response}\n"),
+        &pass,
+        &gpg,
+    );
+
+    let login_request = r#"{"v":1,"registry":{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"},"kind":"login","token":"platanos","args":["path=tokens/{name}/../../{name}"]}"#;
+    plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(
+            "invalid token path template 'tokens/{name}/../../{name}'",
+        ));
+}
+
+/// The store root and GnuPG home can be selected per registry, without relying
+/// on the inherited environment.
+#[test]
+fn test_registry_store_env() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let work = PassHandle::new(&gpg);
+    let home = tempfile::tempdir().unwrap();
+    let hello = r#"{"v":[1]}"#;
+
+    let args = format!(
+        r#"["store-dir={}","gnupghome={}","--direct-gpg"]"#,
+        work.dir().display(),
+        gpg.home_dir().display()
+    );
+    let cmd = || {
+        let mut cmd = plugin_cmd(&pass, &gpg);
+        cmd.env_remove("GNUPGHOME").env("HOME", home.path());
+        cmd
+    };
+
+    let login_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"}},"kind": "login","token": "platanos","args": {args}}}"#,
+    );
+    cmd()
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{}\n", r#"{"Ok":{"kind":"login"}}"#));
+
+    assert_eq!(work.show(&gpg, "cargo-registry/work.token"), "platanos\n");
+    assert!(!pass.dir().join("cargo-registry/work.token.gpg").exists());
+
+    // Read back by decrypting with gpg directly, which must also use the
+    // configured GnuPG home.
+    let read_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"sparse+https://itsallbroken.com/index/","name":"work"}},"kind": "get","operation": "read","args": {args}}}"#,
+    );
+    let token_response = r#"{"Ok":{"kind":"get","token":"platanos","cache":"session","operation_independent":true}}"#;
+    cmd()
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(format!("{hello}\n{token_response}\n"));
+}
+
+/// Exactly 0 or 1 arguments are accepted in the credential config, with more
+/// than 1 causing an error to be returned.
+#[test]
+fn test_request_too_many_args() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","token": "platanos","args": ["1","2"]}"#;
+    run_plugin(
+        format!("{login_request}\n"),
+        &format!(
+            "{hello}\n{response}\n",
+            hello = r#"{"v":[1]}"#,
+            response = r#"{"Err":{"kind":"other","message":"too many arguments specified in cargo credential provider config","caused-by":[]}}"#,
+        ),
+        &pass,
+        &gpg,
+    );
+}
+
+/// The standalone CLI commands resolve the same token paths as the credential
+/// provider.
+#[test]
+fn test_cli() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    cli_cmd(&pass, &gpg, &["show-path", "crates-io"])
+        .assert()
+        .success()
+        .stdout("cargo-registry/crates-io.token\n");
+
+    cli_cmd(
+        &pass,
+        &gpg,
+        &[
+            "show-path",
+            "sparse+https://itsallbroken.com/index/",
+            "--operation=publish",
+        ],
+    )
+    .assert()
+    .success()
+    .stdout("cargo-registry/itsallbroken.com/689322e6387231a7.publish.token\n");
+
+    // Login reads the token from stdin.
+    cli_cmd(&pass, &gpg, &["login", "crates-io"])
+        .write_stdin("platanos\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "token stored in cargo-registry/crates-io.token\n",
+        ));
+
+    cli_cmd(&pass, &gpg, &["login", "crates-io", "--operation=publish"])
+        .write_stdin("platanos\n")
+        .assert()
+        .success();
+
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/crates-io.token"),
+        "platanos\n"
+    );
+
+    cli_cmd(&pass, &gpg, &["list"])
+        .assert()
+        .success()
+        .stdout("cargo-registry/crates-io.publish.token\ncargo-registry/crates-io.token\n");
+
+    cli_cmd(&pass, &gpg, &["status", "crates-io"])
+        .assert()
+        .success()
+        .stdout(
+            "default  cargo-registry/crates-io.token (stored)\n\
+            publish  cargo-registry/crates-io.publish.token (stored)\n\
+            yank     cargo-registry/crates-io.yank.token (not stored)\n\
+            owners   cargo-registry/crates-io.owners.token (not stored)\n",
+        )
+        .stdout(predicate::str::contains("platanos").not());
+
+    cli_cmd(&pass, &gpg, &["logout", "crates-io"])
+        .assert()
+        .success();
+
+    cli_cmd(&pass, &gpg, &["list"])
+        .assert()
+        .success()
+        .stdout("cargo-registry/crates-io.publish.token\n");
+
+    cli_cmd(&pass, &gpg, &["logout", "crates-io"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: cargo-registry/crates-io.token is not in the password store\n",
+        ));
+
+    cli_cmd(&pass, &gpg, &["show-path"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: missing registry name or index URL argument\n",
+        ));
+}
+
+/// Plaintext tokens in cargo's credentials file are imported into the password
+/// store, and optionally removed from the file.
+#[test]
+fn test_cli_import() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let cargo_home = tempfile::tempdir().expect("failed to create tempdir");
+    let credentials = cargo_home.path().join("credentials.toml");
+    let content =
+        "[registry]\ntoken = \"bananas\"\n\n[registries.platanos]\ntoken = \"platanos\"\n";
+    std::fs::write(&credentials, content).unwrap();
+
+    // Without --rewrite, the credentials file is left untouched.
+    cli_cmd(&pass, &gpg, &["import"])
+        .env("CARGO_HOME", cargo_home.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "imported crates-io token into cargo-registry/crates-io.token",
+        ))
+        .stderr(predicate::str::contains("bananas").not());
+
+    assert_eq!(std::fs::read_to_string(&credentials).unwrap(), content);
+    assert_eq!(
+        pass.show(&gpg, "cargo-registry/crates-io.token"),
+        "bananas\n"
+    );
+
+    // Exact paths cannot hold multiple tokens.
+    cli_cmd(&pass, &gpg, &["import", "tokens/bananas"])
+        .env("CARGO_HOME", cargo_home.path())
+        .assert()
+        .failure();
+
+    cli_cmd(&pass, &gpg, &["import", "--rewrite", "imported/"])
+        .env("CARGO_HOME", cargo_home.path())
+        .assert()
+        .success();
+
+    assert_eq!(pass.show(&gpg, "imported/platanos.token"), "platanos\n");
+    assert_eq!(std::fs::read_to_string(&credentials).unwrap(), "");
+    assert_eq!(
+        std::fs::read_to_string(cargo_home.path().join("credentials.toml.bak")).unwrap(),
+        content
+    );
+}
+
+/// Imported tokens are stored using the config file settings of their
+/// (canonical) registry, and doctor checks the environment configured for a
+/// registry.
+#[test]
+fn test_cli_import_doctor_config() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+    let work = PassHandle::new(&gpg);
+
+    let config_dir = tempfile::tempdir().unwrap();
+    let config = config_dir.path().join("config.toml");
+    let missing = config_dir.path().join("missing");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+            [aliases.urls]
+            "sparse+https://index.crates.io/" = "sparse+https://crates.itsallbroken.com/index/"
+
+            [registries."sparse+https://crates.itsallbroken.com/index/"]
+            path = "mirror/crates-io.token"
+
+            [registries.platanos]
+            store-dir = "{}"
+
+            [registries.broken]
+            store-dir = "{}"
+            "#,
+            work.dir().display(),
+            missing.display()
+        ),
+    )
+    .unwrap();
+
+    let cargo_home = tempfile::tempdir().expect("failed to create tempdir");
+    std::fs::write(
+        cargo_home.path().join("credentials.toml"),
+        "[registry]\ntoken = \"bananas\"\n\n[registries.platanos]\ntoken = \"platanos\"\n",
+    )
+    .unwrap();
+
+    cli_cmd(&pass, &gpg, &["import"])
+        .env("CARGO_HOME", cargo_home.path())
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "imported crates-io token into mirror/crates-io.token",
+        ));
+
+    assert_eq!(pass.show(&gpg, "mirror/crates-io.token"), "bananas\n");
+    assert_eq!(
+        work.show(&gpg, "cargo-registry/platanos.token"),
+        "platanos\n"
+    );
+    assert!(!pass
+        .dir()
+        .join("cargo-registry/platanos.token.gpg")
+        .exists());
+
+    cli_cmd(&pass, &gpg, &["doctor", "--registry=broken"])
+        .env("CARGO_HOME", cargo_home.path())
+        .env("CARGO_CREDENTIAL_PASS_CONFIG", &config)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!(
+            "{} does not exist",
+            missing.display()
+        )));
+}
+
+/// CLI commands decrypt tokens with gpg directly when configured, as the
+/// provider does.
+#[test]
+fn test_cli_direct_gpg() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let cargo_home = tempfile::tempdir().expect("failed to create tempdir");
+    std::fs::write(
+        cargo_home.path().join("credentials.toml"),
+        "[registry]\ntoken = \"bananas\"\n",
+    )
+    .unwrap();
+
+    // A pass that stores entries, but cannot read them back.
+    let real = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+        .map(|v| v.join("pass"))
+        .find(|v| v.is_file())
+        .expect("pass not found in PATH");
+    let fake = FakePass::new(&format!(
+        r#"[ "$1" = show ] && exit 1
+        exec {} "$@""#,
+        real.display()
+    ));
+
+    // Import reads each token back after storing it.
+    cli_cmd(&pass, &gpg, &["import"])
+        .env("CARGO_HOME", cargo_home.path())
+        .env("PATH", fake.path_env())
+        .assert()
+        .failure();
+
+    cli_cmd(&pass, &gpg, &["import", "--direct-gpg"])
+        .env("CARGO_HOME", cargo_home.path())
+        .env("PATH", fake.path_env())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "imported crates-io token into cargo-registry/crates-io.token",
+        ));
+}
+
+/// The doctor command checks the pass and gpg environment.
+#[test]
+fn test_cli_doctor() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let cargo_home = tempfile::tempdir().expect("failed to create tempdir");
+    std::fs::write(
+        cargo_home.path().join("config.toml"),
+        "[registry]\nglobal-credential-providers = [\"cargo-credential-pass\"]\n",
+    )
+    .unwrap();
+
+    cli_cmd(&pass, &gpg, &["doctor"])
+        .env("CARGO_HOME", cargo_home.path())
+        .env("GPG_TTY", "/dev/null")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok:      password store found at"))
+        .stdout(predicate::str::contains(
+            "ok:      public key found for recipient bananas@itsallbroken.com",
+        ))
+        .stdout(predicate::str::contains(
+            "ok:      cargo is configured to use this provider",
+        ))
+        .stdout(predicate::str::contains("error:").not());
+
+    // An uninitialised store is reported with a fix.
+    let empty = tempfile::tempdir().expect("failed to create tempdir");
+    cli_cmd(&pass, &gpg, &["doctor"])
+        .env("PASSWORD_STORE_DIR", empty.path().join("missing"))
+        .env("CARGO_HOME", empty.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("does not exist"))
+        .stdout(predicate::str::contains("fix: initialise the store"))
+        .stdout(predicate::str::contains(
+            "warning: cargo is not configured to use cargo-credential-pass",
+        ));
+}
+
+/// Tokens never appear in any plugin output, even when `pass` misbehaves and
+/// echoes them back.
+#[test]
+fn test_secrets_never_leaked() {
+    const SECRET: &str = "s3cr3t-t0ken";
+
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    // A broken pass wrapper that echoes the entry it is given, and prints the
+    // token when reading it, before failing.
+    let fake = FakePass::new(&format!(
+        r#"case "$1" in
+            insert) input=$(cat); echo "$input"; echo "$input" >&2 ;;
+            show) echo "{SECRET}" ;;
+        esac
+        exit 1"#
+    ));
+
+    let login_request = format!(
+        r#"{{"v": 1,"registry": {{"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"}},"kind": "login","token": "{SECRET}","args": []}}"#
+    );
+    assert_not_leaked(
+        plugin_cmd(&pass, &gpg)
+            .env("PATH", fake.path_env())
+            .write_stdin(format!("{login_request}\n"))
+            .assert(),
+        SECRET,
+    )
+    .stdout(predicate::str::contains("<redacted>"));
+
+    // Reading a (present) token fails.
+    std::fs::create_dir_all(pass.dir().join("cargo-registry")).unwrap();
+    std::fs::write(pass.dir().join("cargo-registry/crates-io.token.gpg"), "").unwrap();
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": []}"#;
+    assert_not_leaked(
+        plugin_cmd(&pass, &gpg)
+            .env("PATH", fake.path_env())
+            .write_stdin(format!("{read_request}\n"))
+            .assert(),
+        SECRET,
+    )
+    .stdout(predicate::str::contains("non-zero status code"));
+}
+
+/// A `pass` command that never completes is killed after the configured
+/// timeout, instead of hanging cargo.
+#[test]
+fn test_pass_timeout() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    // A pass that waits forever, as if blocked on a pinentry prompt.
+    let fake = FakePass::new("exec sleep 60");
+
+    let read_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "get","operation": "read","args": ["timeout=1s"]}"#;
+
+    let start = std::time::Instant::now();
+    plugin_cmd(&pass, &gpg)
+        .env("PATH", fake.path_env())
+        .write_stdin(format!("{read_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(
+            "pass did not complete within 1s and was killed",
+        ));
+
+    assert!(start.elapsed() < std::time::Duration::from_secs(30));
+}
+
+/// Logging in without a token prompts for it (or opens an editor) on the
+/// terminal, and fails with a clear error when there is no terminal.
+#[test]
+fn test_login_without_terminal() {
+    let gpg = GpgHandle::default();
+    let pass = PassHandle::new(&gpg);
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","args": []}"#;
+    detached_plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(
+            "no terminal available to prompt for the token - provide the token with 'cargo login <token>' instead",
+        ));
+
+    // The registry login URL is printed before prompting.
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","login-url":"https://itsallbroken.com/me","args": []}"#;
+    detached_plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stderr(predicate::str::contains(
+            "please paste the token found on https://itsallbroken.com/me below",
+        ))
+        .stdout(predicate::str::contains("no terminal available"));
+
+    let login_request = r#"{"v": 1,"registry": {"index-url":"sparse+https://itsallbroken.com/rust-lang/crates.io-index","name":"crates-io"},"kind": "login","args": ["--editor"]}"#;
+    detached_plugin_cmd(&pass, &gpg)
+        .write_stdin(format!("{login_request}\n"))
+        .assert()
+        .stdout(predicate::str::contains(
+            "no terminal available to open an editor - provide the token with 'cargo login <token>' instead",
+        ));
+}
+
+/// Assert `secret` does not appear in either the stdout (the RPC channel) or
+/// stderr of a plugin execution.
+fn assert_not_leaked(assert: Assert, secret: &str) -> Assert {
+    assert
+        .stdout(predicate::str::contains(secret).not())
+        .stderr(predicate::str::contains(secret).not())
+}
+
+fn run_plugin(stdin: String, want_stdout: &str, pass: &PassHandle, gpg: &GpgHandle) {
+    plugin_cmd(pass, gpg)
+        .write_stdin(stdin)
+        .assert()
+        .stdout(predicate::eq(want_stdout.as_bytes()))
+        .stderr(predicate::str::contains("bananas").not()); // Never prints the token
+}
+
+/// Construct a [`Command`] that runs the plugin against the isolated `pass`
+/// and `gpg` environments.
+fn plugin_cmd(pass: &PassHandle, gpg: &GpgHandle) -> Command {
+    cli_cmd(pass, gpg, &["--cargo-plugin"])
+}
+
+/// Construct a [`Command`] that runs the binary with `args` against the
+/// isolated `pass` and `gpg` environments.
+fn cli_cmd(pass: &PassHandle, gpg: &GpgHandle, args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.args(args);
+    isolate(cmd, pass, gpg)
+}
+
+/// Construct a [`Command`] that runs the plugin in a new session without a
+/// controlling terminal, against the isolated `pass` and `gpg` environments.
+fn detached_plugin_cmd(pass: &PassHandle, gpg: &GpgHandle) -> Command {
+    let mut cmd = Command::new("setsid");
+    cmd.arg(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .arg("--cargo-plugin");
+    isolate(cmd, pass, gpg)
+}
+
+/// Configure `cmd` to use the isolated `pass` and `gpg` environments.
+fn isolate(mut cmd: Command, pass: &PassHandle, gpg: &GpgHandle) -> Command {
+    cmd.env_remove("PASSWORD_STORE_SIGNING_KEY")
+        .env_remove("PASSWORD_STORE_DIR")
+        .env_remove("PASSWORD_STORE_GENERATED_LENGTH")
+        .env_remove("PASSWORD_STORE_GPG_OPTS")
+        .env_remove("GPG_TTY")
+        .env_remove("CARGO_CREDENTIAL_PASS_BACKEND")
+        .env_remove("CARGO_CREDENTIAL_PASS_DIRECT_GPG")
+        .env_remove("CARGO_CREDENTIAL_PASS_EXPIRY_WARNING")
+        .env_remove("CARGO_CREDENTIAL_PASS_TIMEOUT")
+        .env_remove("CARGO_CREDENTIAL_PASS_CONFIG")
+        .env_remove("CARGO_CREDENTIAL_PASS_DEBUG")
+        .env("XDG_CONFIG_HOME", gpg.home_dir())
+        .env("PASSWORD_STORE_DIR", pass.dir())
+        .env("GNUPGHOME", gpg.home_dir());
+    cmd
 }